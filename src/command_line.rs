@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::{
+    config::Mode,
+    lib::{error::RickError, export::ExportFormat},
+};
+
+/// What can be typed after `:` in normal mode.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineCommand {
+    /// Write the active tab's results to a file
+    Export(ExportFormat, PathBuf),
+    /// Answer the following queries from somewhere else
+    Backend(Mode),
+    /// Move to the history pane
+    History,
+    /// Save a query under a signature like `top_by_name($n)`, the active
+    /// tab's query when none is given
+    SaveMacro(String, Option<String>),
+    RemoveMacro(String),
+    ListMacros,
+    Help,
+    CloseTab,
+    Quit,
+}
+
+/// The commands and what they do, for the help overlay
+pub const COMMANDS: &[(&str, &str)] = &[
+    (
+        ":export [csv|json] [path]",
+        "write the tab's results, to rick_cli_export.<format> by default",
+    ),
+    (
+        ":backend direct|local|mock",
+        "answer the next queries from another backend",
+    ),
+    (":history", "move to the history"),
+    (
+        ":macro save <name>[($param, ...)] [query]",
+        "save a query, the tab's by default, to run as @name(args)",
+    ),
+    (":macro remove <name>", "forget a macro"),
+    (":macros", "list the macros"),
+    (":close", "close the tab"),
+    (":help", "show this help"),
+    (":quit, :q", "quit"),
+];
+
+/// The file a tab is exported to when no path is given.
+pub fn default_export_path(format: ExportFormat) -> PathBuf {
+    PathBuf::from(format!("rick_cli_export.{}", format))
+}
+
+/// Reads a command line like `export json results.json`, without the `:`.
+pub fn parse(line: &str) -> Result<LineCommand, RickError> {
+    if let Some(definition) = line.trim().strip_prefix("macro save ") {
+        return parse_macro(definition.trim());
+    }
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Err(RickError::Parse("no command given".to_string()));
+    };
+    let arguments: Vec<&str> = words.collect();
+    let command = match (name, arguments.as_slice()) {
+        ("export", []) => {
+            LineCommand::Export(ExportFormat::Csv, default_export_path(ExportFormat::Csv))
+        }
+        ("export", [format]) => {
+            let format = format.parse()?;
+            LineCommand::Export(format, default_export_path(format))
+        }
+        ("export", [format, path]) => LineCommand::Export(format.parse()?, PathBuf::from(path)),
+        ("backend", [mode]) => LineCommand::Backend(Mode::from_str(mode, true).map_err(|_| {
+            RickError::Parse(format!(
+                "unknown backend `{}`, expected direct, local or mock",
+                mode
+            ))
+        })?),
+        ("history", []) => LineCommand::History,
+        ("macro", ["remove", name]) => LineCommand::RemoveMacro(name.to_string()),
+        ("macros", []) => LineCommand::ListMacros,
+        ("help", []) => LineCommand::Help,
+        ("close", []) => LineCommand::CloseTab,
+        ("quit" | "q", []) => LineCommand::Quit,
+        (
+            "export" | "backend" | "history" | "macro" | "macros" | "help" | "close" | "quit" | "q",
+            _,
+        ) => {
+            return Err(RickError::Parse(format!(
+                "wrong arguments for `{}`, see :help",
+                name
+            )))
+        }
+        _ => return Err(RickError::Parse(format!("unknown command `{}`", name))),
+    };
+    Ok(command)
+}
+
+/// Splits `top_by_name($n, $count) NAME($n)::LIMIT($count)` after the
+/// signature, whose parameters may be spaced out.
+fn parse_macro(definition: &str) -> Result<LineCommand, RickError> {
+    let name_end = definition
+        .find(|c: char| c == '(' || c.is_whitespace())
+        .unwrap_or(definition.len());
+    let signature_end = if definition[name_end..].starts_with('(') {
+        definition
+            .find(')')
+            .map(|close| close + 1)
+            .ok_or_else(|| RickError::Parse("missing `)` after the parameters".to_string()))?
+    } else {
+        name_end
+    };
+    let (signature, query) = definition.split_at(signature_end);
+    let query = Some(query.trim())
+        .filter(|query| !query.is_empty())
+        .map(str::to_string);
+    Ok(LineCommand::SaveMacro(signature.to_string(), query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse("export").unwrap(),
+            LineCommand::Export(ExportFormat::Csv, "rick_cli_export.csv".into())
+        );
+        assert_eq!(
+            parse(" export json  out.json ").unwrap(),
+            LineCommand::Export(ExportFormat::Json, "out.json".into())
+        );
+        assert_eq!(
+            parse("backend MOCK").unwrap(),
+            LineCommand::Backend(Mode::Mock)
+        );
+        assert_eq!(parse("q").unwrap(), LineCommand::Quit);
+        assert_eq!(
+            parse("macro save top($n, $count) NAME($n)::SORT(ASC, created)").unwrap(),
+            LineCommand::SaveMacro(
+                "top($n, $count)".into(),
+                Some("NAME($n)::SORT(ASC, created)".into())
+            )
+        );
+        assert_eq!(
+            parse("macro save pilot").unwrap(),
+            LineCommand::SaveMacro("pilot".into(), None)
+        );
+
+        assert!(parse("export xml").is_err());
+        assert!(parse("backend proxy local").is_err());
+        assert_eq!(
+            parse("hsitory").unwrap_err().to_string(),
+            "unknown command `hsitory`"
+        );
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::{
+    keymap::Keymap,
+    lib::{error::RickError, rm_api::DEFAULT_BASE_URL},
+};
+
+const FILE_NAME: &str = "config.toml";
+/// Read from the working directory, so a project can carry its own settings
+const PROJECT_FILE: &str = ".rick_cli.toml";
+/// Top level settings can be set with e.g `RICK_CLI_CACHE_TTL=60`
+const ENV_PREFIX: &str = "RICK_CLI_";
+const ENV_KEYS: &[&str] = &[
+    "mode",
+    "base_url",
+    "timeout",
+    "retries",
+    "cache_ttl",
+    "default_limit",
+    "theme",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Proxy,
+    Direct,
+    /// Answer queries from the mirror downloaded by `sync`
+    Local,
+    Mock,
+}
+
+/// The effective settings, every layer merged over the defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where queries are answered from
+    pub mode: Mode,
+    /// The server direct mode and `sync` talk to
+    pub base_url: String,
+    /// Seconds to wait for an API response before retrying
+    pub timeout: u64,
+    /// How many times a failed API request is retried
+    pub retries: u32,
+    /// Seconds API responses are reused for, 0 turns the cache off
+    pub cache_ttl: u64,
+    /// Added as `LIMIT(n)` to queries that have no LIMIT, to the last query of a plan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_limit: Option<u32>,
+    /// A theme name or theme file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    pub keys: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mode: Mode::Direct,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: 10,
+            retries: 3,
+            cache_ttl: 300,
+            default_limit: None,
+            theme: None,
+            keys: Keymap::default(),
+        }
+    }
+}
+
+/// Settings from one source, e.g a file or the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub source: String,
+    pub table: Table,
+}
+
+impl Layer {
+    pub fn new(source: &str) -> Self {
+        Layer {
+            source: source.to_string(),
+            table: Table::new(),
+        }
+    }
+
+    /// Sets a top level setting, when there is a value.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: Option<T>) -> Result<(), RickError> {
+        if let Some(value) = value {
+            let value = Value::try_from(value).map_err(|err| RickError::Parse(err.to_string()))?;
+            self.table.insert(key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Reads a TOML file, `None` when there is none.
+    pub fn read(path: &Path) -> Result<Option<Self>, RickError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let table = contents
+            .parse::<Table>()
+            .map_err(|err| RickError::Parse(format!("in {}: {}", path.display(), err.message())))?;
+        Ok(Some(Layer {
+            source: path.display().to_string(),
+            table,
+        }))
+    }
+
+    /// The `RICK_CLI_*` variables, numbers read as numbers.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut layer = Layer::new("environment");
+        for key in ENV_KEYS {
+            if let Some(raw) = var(&format!("{}{}", ENV_PREFIX, key.to_uppercase())) {
+                let value = raw
+                    .parse::<i64>()
+                    .map(Value::Integer)
+                    .unwrap_or(Value::String(raw));
+                layer.table.insert(key.to_string(), value);
+            }
+        }
+        layer
+    }
+}
+
+impl Config {
+    /// The files and environment that exist, lowest precedence first: the
+    /// system file, the user file, the project file and the environment.
+    pub fn layers() -> Result<Vec<Layer>, RickError> {
+        let mut files = vec![];
+        if cfg!(unix) {
+            files.push(Path::new("/etc/rick_cli").join(FILE_NAME));
+        }
+        if let Some(dir) = dirs::config_dir() {
+            files.push(dir.join("rick_cli").join(FILE_NAME));
+        }
+        files.push(PathBuf::from(PROJECT_FILE));
+
+        let mut layers = vec![];
+        for path in files {
+            layers.extend(Layer::read(&path)?);
+        }
+        layers.push(Layer::from_env(|name| std::env::var(name).ok()));
+        Ok(layers)
+    }
+
+    /// Merges `layers` over the defaults, later layers winning key by key.
+    pub fn merge(layers: &[Layer]) -> Result<Self, RickError> {
+        let mut merged = Table::new();
+        for layer in layers {
+            // checked on its own first, so a mistake names the file it is in
+            parse(layer.table.clone())
+                .map_err(|err| RickError::Parse(format!("in {}: {}", layer.source, err)))?;
+            merge(&mut merged, layer.table.clone());
+        }
+        let config = parse(merged)?;
+        config.keys.validate()?;
+        Ok(config)
+    }
+
+    /// The effective settings as TOML, preceded by the layers they came from.
+    pub fn show(&self, layers: &[Layer]) -> Result<String, RickError> {
+        let mut shown = String::new();
+        for layer in layers.iter().filter(|layer| !layer.table.is_empty()) {
+            shown.push_str(&format!("# from {}\n", layer.source));
+        }
+        shown.push_str(&toml::to_string(self).map_err(|err| RickError::Parse(err.to_string()))?);
+        Ok(shown)
+    }
+}
+
+fn parse(table: Table) -> Result<Config, RickError> {
+    Value::Table(table)
+        .try_into()
+        .map_err(|err: toml::de::Error| RickError::Parse(err.message().to_string()))
+}
+
+/// Copies `layer` into `base`, merging tables like `[keys]` key by key.
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(source: &str, contents: &str) -> Layer {
+        Layer {
+            source: source.into(),
+            table: contents.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let mut cli = Layer::new("command line");
+        cli.set("cache_ttl", Some(0)).unwrap();
+        cli.set("mode", Some(Mode::Mock)).unwrap();
+        cli.set::<u32>("default_limit", None).unwrap();
+        let layers = [
+            layer(
+                "system",
+                "mode = \"local\"\ntimeout = 30\n[keys]\nquit = \"ctrl-c\"",
+            ),
+            layer("user", "default_limit = 5\n[keys]\nhelp = \"f1\""),
+            Layer::from_env(|name| (name == "RICK_CLI_TIMEOUT").then(|| "60".to_string())),
+            cli,
+        ];
+        let config = Config::merge(&layers).unwrap();
+        assert_eq!(config.mode, Mode::Mock);
+        assert_eq!(config.timeout, 60);
+        assert_eq!(config.cache_ttl, 0);
+        assert_eq!(config.default_limit, Some(5));
+        assert_eq!(config.keys.quit, "ctrl-c");
+        assert_eq!(config.keys.help, "f1");
+        assert_eq!(config.keys.edit, "e");
+        assert_eq!(config.base_url, DEFAULT_BASE_URL);
+
+        let shown = config.show(&layers).unwrap();
+        assert!(shown.starts_with("# from system\n# from user\n# from environment\n"));
+        assert!(shown.contains("mode = \"mock\""));
+        assert!(shown.contains("[keys]"));
+        assert_eq!(Config::merge(&[layer("shown", &shown)]).unwrap(), config);
+    }
+
+    #[test]
+    fn reports_the_layer_at_fault() {
+        let err = Config::merge(&[layer("user", "cache_tll = 5")]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("in user: unknown field `cache_tll`"),
+            "{}",
+            err
+        );
+        assert!(Config::merge(&[layer("user", "[keys]\nhelp = \"q\"")]).is_err());
+        assert!(Config::merge(&[]).is_ok());
+    }
+}
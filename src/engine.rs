@@ -0,0 +1,775 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc},
+};
+
+use image::RgbaImage;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use crate::lib::{
+    error::RickError,
+    explain,
+    export::{export_to_file, ExportFormat},
+    planner::{query_plan, Plan},
+    query_language::macros::Macros,
+    rm_api::{
+        request::{
+            direct_request::DirectRequest, local_request::LocalRequest, mock_request::MockRequest,
+            transport::Transport,
+        },
+        response::RMResponseEnum,
+        DEFAULT_BASE_URL,
+    },
+    QueryStats,
+};
+use crate::{config::Mode, portrait};
+
+/// Identifies the tab a query runs in, chosen by the UI.
+pub type TabId = u64;
+
+/// What the UI asks the engine to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run a query in a tab, replacing whatever the tab showed
+    RunQuery(TabId, String),
+    /// Abort the query in flight in a tab, if any
+    Cancel(TabId),
+    /// Re-run the tab's last query for the page after the one shown
+    NextPage(TabId),
+    /// Re-run the tab's last query for the page before the one shown
+    PrevPage(TabId),
+    /// Write the tab's last result to a file
+    Export(TabId, ExportFormat, PathBuf),
+    /// Forget the tab, aborting its query
+    CloseTab(TabId),
+    /// Download and decode the image at a URL, e.g a character's portrait
+    FetchPortrait(String),
+    /// Answer the following queries from another backend
+    SwitchBackend(Mode),
+    /// Save a query as a macro, under a signature like `top_by_name($n)`
+    SaveMacro(String, String),
+    RemoveMacro(String),
+    ListMacros,
+    Quit,
+}
+
+/// What the engine reports back, tagged with the tab it belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Started(TabId, String),
+    /// The query reached a new stage, e.g waiting for the server
+    Progress(TabId, String),
+    /// The result and how the query went
+    Completed(TabId, RMResponseEnum, QueryStats),
+    Failed(TabId, RickError),
+    /// The path written to and the number of rows, or why exporting failed
+    Exported(Result<(PathBuf, usize), RickError>),
+    /// The image at a URL asked for with `FetchPortrait`, or why it could not be shown
+    Portrait(String, Result<RgbaImage, RickError>),
+    /// The name of the backend switched to, or why it is not available
+    BackendSwitched(Result<String, RickError>),
+    /// What became of a macro command, to show the user
+    Macros(Result<String, RickError>),
+    /// What a query prefixed with `EXPLAIN` would request and do, instead of its result
+    Explained(TabId, String),
+}
+
+#[derive(Clone)]
+pub enum Backend {
+    Direct(DirectRequest),
+    Local(LocalRequest),
+    Mock,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Direct(_) => "Direct",
+            Backend::Local(_) => "Local",
+            Backend::Mock => "Mock",
+        }
+    }
+
+    /// The server requests are built for; the local and mock backends
+    /// answer URLs of the public API.
+    pub fn base_url(&self) -> &str {
+        match self {
+            Backend::Direct(direct_request) => direct_request.base_url(),
+            Backend::Local(_) | Backend::Mock => DEFAULT_BASE_URL,
+        }
+    }
+}
+
+type Finished = (TabId, u64, Result<(RMResponseEnum, QueryStats), RickError>);
+
+struct Running {
+    query: String,
+    handle: JoinHandle<()>,
+    generation: u64,
+}
+
+/// Runs queries off the UI thread. Tabs run independently, but a new query
+/// in a tab cancels the one in flight there.
+pub struct Engine {
+    backend: Backend,
+    events: Sender<Event>,
+    running: HashMap<TabId, Running>,
+    /// Incremented for every submitted query, so a superseded query that
+    /// finishes late is ignored
+    generation: u64,
+    /// The query and result each tab shows
+    last: HashMap<TabId, (String, RMResponseEnum)>,
+    /// Downloads portraits, `None` when there is no network to download them from
+    images: Option<Arc<dyn Transport>>,
+    /// Added as a LIMIT to queries without one, the last query of a plan
+    default_limit: Option<u32>,
+    /// The backends that can be switched to besides mock, which always can
+    backends: HashMap<Mode, Backend>,
+    /// Expanded in every query before it runs
+    macros: Macros,
+}
+
+impl Engine {
+    pub fn new(backend: Backend, events: Sender<Event>) -> Self {
+        Engine {
+            backend,
+            events,
+            running: HashMap::new(),
+            generation: 0,
+            last: HashMap::new(),
+            images: None,
+            default_limit: None,
+            backends: HashMap::new(),
+            macros: Macros::in_memory(),
+        }
+    }
+
+    /// Downloads portraits through `transport`, so they share its cache.
+    pub fn with_images(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.images = Some(transport);
+        self
+    }
+
+    pub fn with_default_limit(mut self, limit: Option<u32>) -> Self {
+        self.default_limit = limit;
+        self
+    }
+
+    /// Lets `SwitchBackend` switch to `backend`.
+    pub fn with_backend(mut self, mode: Mode, backend: Backend) -> Self {
+        self.backends.insert(mode, backend);
+        self
+    }
+
+    pub fn with_macros(mut self, macros: Macros) -> Self {
+        self.macros = macros;
+        self
+    }
+
+    /// Handles commands until `Quit` arrives or every sender is dropped.
+    pub async fn run(mut self, mut commands: UnboundedReceiver<Command>) {
+        let (finished_tx, mut finished_rx) = mpsc::unbounded_channel::<Finished>();
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    None | Some(Command::Quit) => break,
+                    Some(command) => self.handle(command, &finished_tx),
+                },
+                Some((tab, generation, result)) = finished_rx.recv() => {
+                    self.finish(tab, generation, result);
+                }
+            }
+        }
+        for (_, running) in self.running.drain() {
+            running.handle.abort();
+        }
+    }
+
+    fn handle(&mut self, command: Command, finished: &UnboundedSender<Finished>) {
+        match command {
+            Command::RunQuery(tab, query) => self.start(tab, query, finished),
+            Command::Cancel(tab) => self.cancel(tab),
+            Command::NextPage(tab) => self.turn_page(tab, true, finished),
+            Command::PrevPage(tab) => self.turn_page(tab, false, finished),
+            Command::Export(tab, format, path) => {
+                let exported = match self.last.get(&tab) {
+                    Some((_, response)) => {
+                        export_to_file(response, format, &path).map(|rows| (path, rows))
+                    }
+                    None => Err(RickError::Io(
+                        "there is no result to export yet".to_string(),
+                    )),
+                };
+                self.send(Event::Exported(exported));
+            }
+            Command::CloseTab(tab) => {
+                if let Some(running) = self.running.remove(&tab) {
+                    running.handle.abort();
+                }
+                self.last.remove(&tab);
+            }
+            Command::FetchPortrait(url) => self.fetch_portrait(url),
+            Command::SwitchBackend(mode) => {
+                let backend = match mode {
+                    Mode::Mock => Some(Backend::Mock),
+                    mode => self.backends.get(&mode).cloned(),
+                };
+                let switched = match backend {
+                    Some(backend) => {
+                        self.backend = backend;
+                        Ok(self.backend.name().to_string())
+                    }
+                    None => Err(RickError::Parse(format!(
+                        "the {:?} backend is not available",
+                        mode
+                    ))),
+                };
+                self.send(Event::BackendSwitched(switched));
+            }
+            Command::SaveMacro(signature, query) => {
+                let saved = self
+                    .macros
+                    .save(&signature, &query)
+                    .map(|signature| format!("Saved {}", signature));
+                self.send(Event::Macros(saved));
+            }
+            Command::RemoveMacro(name) => {
+                let removed = match self.macros.remove(&name) {
+                    Ok(true) => Ok(format!("Removed {}", name)),
+                    Ok(false) => Err(RickError::Parse(format!("unknown macro `{}`", name))),
+                    Err(err) => Err(err),
+                };
+                self.send(Event::Macros(removed));
+            }
+            Command::ListMacros => {
+                let signatures: Vec<String> = self
+                    .macros
+                    .iter()
+                    .map(|(name, saved)| saved.signature(name))
+                    .collect();
+                let listed = if signatures.is_empty() {
+                    "No macros saved yet".to_string()
+                } else {
+                    format!("Macros: {}", signatures.join(", "))
+                };
+                self.send(Event::Macros(Ok(listed)));
+            }
+            Command::Quit => {}
+        }
+    }
+
+    /// Runs the tab's last query for the adjacent page, if the API says there is one.
+    fn turn_page(&mut self, tab: TabId, forward: bool, finished: &UnboundedSender<Finished>) {
+        let Some((query, response)) = self.last.get(&tab) else {
+            return;
+        };
+        let info = response.info();
+        let adjacent = if forward { &info.next } else { &info.prev };
+        if adjacent.is_none() {
+            return;
+        }
+        // only the last query of a plan is paged, the ones it depends on are run again as they are
+        let plan = Plan::parse(query).and_then(|plan| {
+            let page = plan.page()?;
+            plan.with_page(if forward { page + 1 } else { page - 1 })
+        });
+        match plan {
+            Ok(plan) => self.start(tab, plan.to_string(), finished),
+            Err(err) => self.send(Event::Failed(tab, err)),
+        }
+    }
+
+    fn start(&mut self, tab: TabId, query: String, finished: &UnboundedSender<Finished>) {
+        let (explain, query) = match query.trim_start().strip_prefix("EXPLAIN ") {
+            Some(query) => (true, query.trim().to_string()),
+            None => (false, query),
+        };
+        let plan = match Plan::parse_with(&query, &self.macros) {
+            Ok(plan) => plan,
+            Err(err) => {
+                self.cancel(tab);
+                self.send(Event::Started(tab, query));
+                self.send(Event::Failed(tab, err));
+                return;
+            }
+        };
+        // a query that does not parse is left for query_plan to report
+        let plan = match self.default_limit {
+            Some(limit) => plan.with_default_limit(limit).unwrap_or(plan),
+            None => plan,
+        };
+        let query = plan.to_string();
+        self.cancel(tab);
+        if explain {
+            // the tab shows the explanation now, there is no result to page through
+            self.last.remove(&tab);
+            self.send(Event::Started(tab, format!("EXPLAIN {}", query)));
+            match explain::explain(self.backend.base_url(), &plan) {
+                Ok(explanation) => self.send(Event::Explained(tab, explanation.to_string())),
+                Err(err) => self.send(Event::Failed(tab, err)),
+            }
+            return;
+        }
+        self.generation += 1;
+        self.send(Event::Started(tab, query.clone()));
+
+        let generation = self.generation;
+        let backend = self.backend.clone();
+        let events = self.events.clone();
+        let finished = finished.clone();
+        let handle = tokio::spawn(async move {
+            let progress = |stage: &str| {
+                let _ = events.send(Event::Progress(tab, stage.to_string()));
+            };
+            let result = match backend {
+                Backend::Direct(direct_request) => {
+                    query_plan(direct_request, &plan, progress).await
+                }
+                Backend::Local(local_request) => {
+                    query_plan(local_request, &plan, progress).await
+                }
+                Backend::Mock => query_plan(MockRequest, &plan, progress).await,
+            };
+            let _ = finished.send((tab, generation, result));
+        });
+        self.running.insert(
+            tab,
+            Running {
+                query,
+                handle,
+                generation,
+            },
+        );
+    }
+
+    fn fetch_portrait(&self, url: String) {
+        let Some(images) = self.images.clone() else {
+            self.send(Event::Portrait(
+                url,
+                Err(RickError::Network(
+                    "portraits are not downloaded in this mode".to_string(),
+                )),
+            ));
+            return;
+        };
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            let image = images
+                .get_bytes(&url)
+                .await
+                .and_then(|bytes| portrait::decode(&bytes));
+            let _ = events.send(Event::Portrait(url, image));
+        });
+    }
+
+    fn cancel(&mut self, tab: TabId) {
+        if let Some(running) = self.running.remove(&tab) {
+            if !running.handle.is_finished() {
+                running.handle.abort();
+                self.send(Event::Failed(tab, RickError::Cancelled));
+            }
+        }
+    }
+
+    fn finish(
+        &mut self,
+        tab: TabId,
+        generation: u64,
+        result: Result<(RMResponseEnum, QueryStats), RickError>,
+    ) {
+        match self.running.get(&tab) {
+            Some(running) if running.generation == generation => {}
+            _ => return,
+        }
+        let Some(running) = self.running.remove(&tab) else {
+            return;
+        };
+        match result {
+            Ok((response, stats)) => {
+                self.last.insert(tab, (running.query, response.clone()));
+                self.send(Event::Completed(tab, response, stats));
+            }
+            Err(err) => self.send(Event::Failed(tab, err)),
+        }
+    }
+
+    fn send(&self, event: Event) {
+        // the UI going away is how the app shuts down, so there is no one left to tell
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::request::transport::TransportResponse;
+    use async_trait::async_trait;
+    use std::sync::mpsc::{channel, Receiver};
+
+    /// Serves the portrait fixture for every download
+    struct FixtureTransport;
+
+    #[async_trait]
+    impl Transport for FixtureTransport {
+        async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+            Err(RickError::Network(format!("unexpected GET {}", url)))
+        }
+
+        async fn get_bytes(&self, _url: &str) -> Result<Vec<u8>, RickError> {
+            Ok(include_bytes!("fixtures/portrait.png").to_vec())
+        }
+    }
+
+    fn completed(events: &Receiver<Event>) -> (TabId, RMResponseEnum) {
+        loop {
+            match events.recv().unwrap() {
+                Event::Completed(tab, response, _) => return (tab, response),
+                Event::Failed(tab, err) => panic!("tab {} failed: {}", tab, err),
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_queries_and_pages() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(Engine::new(Backend::Mock, events_tx).run(commands_rx));
+
+        commands
+            .send(Command::RunQuery(1, "CHARACTERS::LIMIT(3)".into()))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "CHARACTERS::LIMIT(3)".into())
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => assert_eq!(page.results.len(), 3),
+            _ => panic!(),
+        }
+
+        commands.send(Command::NextPage(1)).unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "CHARACTERS::PAGE(2)::LIMIT(3)".into())
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => {
+                assert_eq!(page.results[0].name, "Aqua Morty");
+            }
+            _ => panic!(),
+        }
+
+        commands.send(Command::PrevPage(1)).unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "CHARACTERS::PAGE(1)::LIMIT(3)".into())
+        );
+        completed(&events);
+        // there is nothing before the first page
+        commands.send(Command::PrevPage(1)).unwrap();
+        commands
+            .send(Command::Export(1, ExportFormat::Csv, "/".into()))
+            .unwrap();
+        assert!(matches!(events.recv().unwrap(), Event::Exported(Err(_))));
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pages_the_last_query_of_a_plan() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(Engine::new(Backend::Mock, events_tx).run(commands_rx));
+
+        commands
+            .send(Command::RunQuery(
+                1,
+                "LET c = CHARACTERS::IDS(1); CHARACTERS::CONTAINS(species, $c.species)::LIMIT(2)"
+                    .into(),
+            ))
+            .unwrap();
+        events.recv().unwrap();
+        let first = match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => page.results[0].id,
+            _ => panic!(),
+        };
+
+        commands.send(Command::NextPage(1)).unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(
+                1,
+                "LET c = CHARACTERS::IDS(1); CHARACTERS::PAGE(2)::CONTAINS(species, $c.species)::LIMIT(2)".into()
+            )
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => assert_ne!(page.results[0].id, first),
+            _ => panic!(),
+        }
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tabs_run_independently() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(Engine::new(Backend::Mock, events_tx).run(commands_rx));
+
+        commands
+            .send(Command::RunQuery(1, "EPISODES".into()))
+            .unwrap();
+        commands
+            .send(Command::RunQuery(2, "LOCATIONS".into()))
+            .unwrap();
+        let mut finished = [completed(&events), completed(&events)];
+        finished.sort_by_key(|(tab, _)| *tab);
+        assert!(matches!(finished[0], (1, RMResponseEnum::Episodes(_))));
+        assert!(matches!(finished[1], (2, RMResponseEnum::Locations(_))));
+
+        commands.send(Command::CloseTab(1)).unwrap();
+        commands
+            .send(Command::Export(1, ExportFormat::Csv, "unused.csv".into()))
+            .unwrap();
+        assert!(matches!(events.recv().unwrap(), Event::Exported(Err(_))));
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_failures_and_missing_exports() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(Engine::new(Backend::Mock, events_tx).run(commands_rx));
+
+        commands
+            .send(Command::Export(1, ExportFormat::Csv, "unused.csv".into()))
+            .unwrap();
+        assert!(matches!(events.recv().unwrap(), Event::Exported(Err(_))));
+
+        commands
+            .send(Command::RunQuery(1, "NOTAROOT".into()))
+            .unwrap();
+        loop {
+            if let Event::Failed(_, err) = events.recv().unwrap() {
+                // parse errors are reported as diagnostics, like the other problems
+                assert_eq!(err.to_string(), "Operation not found: `NOTAROOT`");
+                assert!(matches!(err, RickError::Invalid(_)));
+                break;
+            }
+        }
+
+        drop(commands);
+        engine.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fetches_portraits() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(
+            Engine::new(Backend::Mock, events_tx)
+                .with_images(Arc::new(FixtureTransport))
+                .run(commands_rx),
+        );
+
+        commands
+            .send(Command::FetchPortrait("avatar/1.jpeg".into()))
+            .unwrap();
+        match events.recv().unwrap() {
+            Event::Portrait(url, Ok(image)) => {
+                assert_eq!(url, "avatar/1.jpeg");
+                assert_eq!(image.dimensions(), (4, 4));
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+
+        // without a transport there is nothing to download with
+        let (events_tx, events) = channel();
+        Engine::new(Backend::Mock, events_tx).fetch_portrait("avatar/1.jpeg".into());
+        assert!(matches!(
+            events.recv().unwrap(),
+            Event::Portrait(_, Err(RickError::Network(_)))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn switches_backends() {
+        let (events_tx, events) = channel();
+        let mut engine = Engine::new(Backend::Direct(DirectRequest::default()), events_tx);
+        let (finished, _) = mpsc::unbounded_channel();
+
+        engine.handle(Command::SwitchBackend(Mode::Local), &finished);
+        assert!(matches!(
+            events.recv().unwrap(),
+            Event::BackendSwitched(Err(RickError::Parse(_)))
+        ));
+        engine.handle(Command::SwitchBackend(Mode::Mock), &finished);
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::BackendSwitched(Ok("Mock".into()))
+        );
+        assert_eq!(engine.backend.name(), "Mock");
+
+        let mut engine = engine.with_backend(Mode::Direct, Backend::Direct(DirectRequest::default()));
+        engine.handle(Command::SwitchBackend(Mode::Direct), &finished);
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::BackendSwitched(Ok("Direct".into()))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expands_macros() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(Engine::new(Backend::Mock, events_tx).run(commands_rx));
+
+        commands
+            .send(Command::SaveMacro("first($n)".into(), "LIMIT($n)".into()))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Macros(Ok("Saved @first($n)".into()))
+        );
+        commands
+            .send(Command::RunQuery(1, "EPISODES::@first(2)".into()))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "EPISODES::LIMIT(2)".into())
+        );
+        completed(&events);
+        commands
+            .send(Command::RunQuery(
+                1,
+                "LET e = EPISODES::@first(1); CHARACTERS::IDS($e.characters)::@first(2)".into(),
+            ))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(
+                1,
+                "LET e = EPISODES::LIMIT(1); CHARACTERS::IDS($e.characters)::LIMIT(2)".into()
+            )
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => assert_eq!(page.results.len(), 2),
+            _ => panic!(),
+        }
+
+        commands
+            .send(Command::RunQuery(2, "EPISODES::@first".into()))
+            .unwrap();
+        events.recv().unwrap();
+        assert!(matches!(
+            events.recv().unwrap(),
+            Event::Failed(2, RickError::Parse(_))
+        ));
+
+        commands.send(Command::ListMacros).unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Macros(Ok("Macros: @first($n)".into()))
+        );
+        commands.send(Command::RemoveMacro("first".into())).unwrap();
+        commands.send(Command::RemoveMacro("first".into())).unwrap();
+        assert!(matches!(events.recv().unwrap(), Event::Macros(Ok(_))));
+        assert!(matches!(events.recv().unwrap(), Event::Macros(Err(_))));
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn explains_queries_without_running_them() {
+        let (events_tx, events) = channel();
+        let mut engine = Engine::new(Backend::Mock, events_tx);
+        let (finished, _) = mpsc::unbounded_channel();
+
+        engine.handle(
+            Command::RunQuery(1, "EXPLAIN EPISODES::NAME(Pilot)".into()),
+            &finished,
+        );
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "EXPLAIN EPISODES::NAME(Pilot)".into())
+        );
+        match events.recv().unwrap() {
+            Event::Explained(1, explanation) => {
+                assert!(
+                    explanation.contains("GET https://rickandmortyapi.com/api/episode/?name=Pilot")
+                )
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+        assert!(engine.running.is_empty());
+
+        engine.handle(
+            Command::RunQuery(1, "EXPLAIN NAME(Pilot)".into()),
+            &finished,
+        );
+        events.recv().unwrap();
+        assert!(matches!(
+            events.recv().unwrap(),
+            Event::Failed(1, RickError::Parse(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn adds_the_default_limit() {
+        let (events_tx, events) = channel();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let engine = tokio::spawn(
+            Engine::new(Backend::Mock, events_tx)
+                .with_default_limit(Some(2))
+                .run(commands_rx),
+        );
+
+        commands
+            .send(Command::RunQuery(1, "CHARACTERS".into()))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(1, "CHARACTERS::LIMIT(2)".into())
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Characters(page)) => assert_eq!(page.results.len(), 2),
+            _ => panic!(),
+        }
+
+        // only the query whose result is shown is limited
+        commands
+            .send(Command::RunQuery(
+                1,
+                "LET e = EPISODES::NAME(Pilot); CHARACTERS::IDS($e.characters) | EPISODES".into(),
+            ))
+            .unwrap();
+        assert_eq!(
+            events.recv().unwrap(),
+            Event::Started(
+                1,
+                "LET e = EPISODES::NAME(Pilot); CHARACTERS::IDS($e.characters) | EPISODES::LIMIT(2)"
+                    .into()
+            )
+        );
+        match completed(&events) {
+            (1, RMResponseEnum::Episodes(page)) => assert_eq!(page.results.len(), 2),
+            _ => panic!(),
+        }
+
+        commands.send(Command::Quit).unwrap();
+        engine.await.unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::lib::error::RickError;
+
+/// What a configurable key does in normal mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Edit,
+    Quit,
+    NextPage,
+    PrevPage,
+    Cancel,
+    Export,
+    CloseTab,
+    Compare,
+    Help,
+    ToggleHistory,
+    Up,
+    Down,
+    /// Hide the rows that do not contain some text
+    Filter,
+    /// Type a command like `:export json`
+    CommandLine,
+}
+
+/// The keys of the normal mode actions, written like `q`, `ctrl-c` or `f5`.
+/// The arrow keys, Enter and Esc always work on top of these.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keymap {
+    pub edit: String,
+    pub quit: String,
+    pub next_page: String,
+    pub prev_page: String,
+    pub cancel: String,
+    pub export: String,
+    pub close_tab: String,
+    pub compare: String,
+    pub help: String,
+    pub history: String,
+    pub up: String,
+    pub down: String,
+    pub filter: String,
+    pub command: String,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            edit: "e".into(),
+            quit: "q".into(),
+            next_page: "n".into(),
+            prev_page: "p".into(),
+            cancel: "c".into(),
+            export: "x".into(),
+            close_tab: "w".into(),
+            compare: "s".into(),
+            help: "?".into(),
+            history: "h".into(),
+            up: "k".into(),
+            down: "j".into(),
+            filter: "/".into(),
+            command: ":".into(),
+        }
+    }
+}
+
+impl Keymap {
+    fn bindings(&self) -> [(Action, &str); 14] {
+        [
+            (Action::Edit, &self.edit),
+            (Action::Quit, &self.quit),
+            (Action::NextPage, &self.next_page),
+            (Action::PrevPage, &self.prev_page),
+            (Action::Cancel, &self.cancel),
+            (Action::Export, &self.export),
+            (Action::CloseTab, &self.close_tab),
+            (Action::Compare, &self.compare),
+            (Action::Help, &self.help),
+            (Action::ToggleHistory, &self.history),
+            (Action::Up, &self.up),
+            (Action::Down, &self.down),
+            (Action::Filter, &self.filter),
+            (Action::CommandLine, &self.command),
+        ]
+    }
+
+    /// The key bound to `action`, as written in the config.
+    pub fn key(&self, action: Action) -> &str {
+        self.bindings()
+            .into_iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, key)| key)
+            .unwrap_or_default()
+    }
+
+    /// Checks that every key can be read and that no key does two things.
+    pub fn validate(&self) -> Result<(), RickError> {
+        let mut seen = vec![];
+        for (_, spec) in self.bindings() {
+            let key = parse_key(spec)?;
+            if seen.contains(&key) {
+                return Err(RickError::Parse(format!("`{}` is bound twice", spec)));
+            }
+            seen.push(key);
+        }
+        Ok(())
+    }
+
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, spec)| parse_key(spec).is_ok_and(|bound| matches(bound, key)))
+            .map(|(action, _)| action)
+    }
+}
+
+/// Whether a pressed key is the bound one. Shift is part of the character
+/// for letters and symbols, so it is only compared for the other keys.
+fn matches((code, modifiers): (KeyCode, KeyModifiers), key: &KeyEvent) -> bool {
+    match code {
+        KeyCode::Char(_) => code == key.code && modifiers == key.modifiers - KeyModifiers::SHIFT,
+        _ => code == key.code && modifiers == key.modifiers,
+    }
+}
+
+/// Reads a key like `x`, `ctrl-r`, `alt-enter` or `pagedown`.
+pub fn parse_key(spec: &str) -> Result<(KeyCode, KeyModifiers), RickError> {
+    let invalid = || RickError::Parse(format!("unknown key `{}`", spec));
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    // a lone `-` is a key, not a separator
+    while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(invalid()),
+        };
+        rest = key;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            function => match function.strip_prefix('f').map(str::parse::<u8>) {
+                Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+                _ => return Err(invalid()),
+            },
+        },
+    };
+    Ok((code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            parse_key("q").unwrap(),
+            (KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse_key("-").unwrap(),
+            (KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse_key("ctrl-alt-x").unwrap(),
+            (
+                KeyCode::Char('x'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(
+            parse_key("F5").unwrap(),
+            (KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert!(parse_key("hyper-x").is_err());
+        assert!(parse_key("f13").is_err());
+    }
+
+    #[test]
+    fn finds_actions() {
+        let keymap = Keymap {
+            quit: "ctrl-c".into(),
+            next_page: "N".into(),
+            ..Default::default()
+        };
+        keymap.validate().unwrap();
+        let press = |code, modifiers| keymap.action(&KeyEvent::new(code, modifiers));
+        assert_eq!(
+            press(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Action::Quit)
+        );
+        assert_eq!(press(KeyCode::Char('q'), KeyModifiers::NONE), None);
+        assert_eq!(
+            press(KeyCode::Char('N'), KeyModifiers::SHIFT),
+            Some(Action::NextPage)
+        );
+        assert_eq!(keymap.key(Action::NextPage), "N");
+
+        let clashing = Keymap {
+            help: "e".into(),
+            ..Default::default()
+        };
+        assert!(clashing.validate().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::{error::Error, fmt};
+
+use super::query_language::validate::Diagnostic;
+
+/// Every way a query can fail, from parsing the input to decoding the response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RickError {
+    /// The query string could not be parsed
+    Parse(String),
+    /// The server answered with a non-success status and no API error body
+    HttpStatus { status: u16, url: String },
+    /// The server answered with an error body, e.g `{"error": "There is nothing here"}`
+    Api { status: u16, message: String },
+    /// The response body did not have the expected shape
+    Decode(String),
+    /// An operation referenced a field the entity does not have
+    UnknownField { entity: String, field: String },
+    /// The query parsed but does not make sense, e.g `DIMENSION` on EPISODES
+    Invalid(Vec<Diagnostic>),
+    /// The query was superseded or aborted before it finished
+    Cancelled,
+    /// The request never got a response, e.g a timeout or a refused connection
+    Network(String),
+    /// Reading or writing local files failed
+    Io(String),
+    /// A replayed cassette has no response for the request
+    NotRecorded(String),
+}
+
+impl RickError {
+    /// Short label used to tell errors apart in the UI.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RickError::Parse(_) => "Parse error",
+            RickError::HttpStatus { .. } => "HTTP error",
+            RickError::Api { .. } => "API error",
+            RickError::Decode(_) => "Decode error",
+            RickError::UnknownField { .. } => "Unknown field",
+            RickError::Invalid(_) => "Invalid query",
+            RickError::Cancelled => "Cancelled",
+            RickError::Network(_) => "Network error",
+            RickError::Io(_) => "IO error",
+            RickError::NotRecorded(_) => "Not recorded",
+        }
+    }
+}
+
+impl fmt::Display for RickError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RickError::Parse(message) => write!(f, "{}", message),
+            RickError::HttpStatus { status, url } => write!(f, "HTTP {} from {}", status, url),
+            RickError::Api { status, message } => write!(f, "{} (HTTP {})", message, status),
+            RickError::Decode(message) => write!(f, "could not decode response: {}", message),
+            RickError::UnknownField { entity, field } => {
+                write!(f, "{} have no field named `{}`", entity, field)
+            }
+            RickError::Invalid(diagnostics) => {
+                let messages: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+                write!(f, "{}", messages.join("; "))
+            }
+            RickError::Cancelled => write!(f, "query was cancelled"),
+            RickError::Network(message) => write!(f, "{}", message),
+            RickError::Io(message) => write!(f, "{}", message),
+            RickError::NotRecorded(url) => write!(f, "no recorded interaction for GET {}", url),
+        }
+    }
+}
+
+impl Error for RickError {}
+
+impl From<reqwest::Error> for RickError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            RickError::Decode(err.to_string())
+        } else {
+            RickError::Network(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for RickError {
+    fn from(err: serde_json::Error) -> Self {
+        RickError::Decode(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for RickError {
+    fn from(err: std::io::Error) -> Self {
+        RickError::Io(err.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for RickError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(_) => RickError::Cancelled,
+        }
+    }
+}
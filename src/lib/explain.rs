@@ -0,0 +1,235 @@
+use std::fmt;
+
+use super::{
+    error::RickError,
+    planner::{root_of, with_stand_ins, Plan},
+    query_language::{
+        operation::OperationEnum,
+        operation_list::OperationList,
+        validate::{parse_and_validate, Diagnostic, Severity},
+    },
+    rm_api::request::builder::build_url_from_operations,
+};
+
+/// How one query of a plan runs: what is asked of the server and what is
+/// done with the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageExplanation {
+    /// `$name` for the queries of a `LET`, empty for the shown pipeline
+    pub binding: Option<String>,
+    pub query: String,
+    /// The URL requested, with `{...}` for the values only known once the
+    /// queries before it ran
+    pub url: String,
+    /// The operations applied to the fetched page, in order
+    pub local: Vec<String>,
+    /// Problems found by `validate`, plus hints about how the query runs
+    pub diagnostics: Vec<Diagnostic>,
+    /// Whether the ids it selects are only known once the queries before it
+    /// ran, so that it fetches nothing when there are none
+    pub ids_may_be_empty: bool,
+}
+
+/// What running a query, or a plan of several, would do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub stages: Vec<StageExplanation>,
+}
+
+impl Explanation {
+    /// The fewest and the most pages fetched: every query fetches a single
+    /// page, except one given no ids, which fetches nothing. A query
+    /// answered from the cache fetches nothing either.
+    pub fn page_fetches(&self) -> (usize, usize) {
+        let certain = self
+            .stages
+            .iter()
+            .filter(|stage| !stage.ids_may_be_empty)
+            .count();
+        (certain, self.stages.len())
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stage in &self.stages {
+            match &stage.binding {
+                Some(name) => writeln!(f, "${} = {}", name, stage.query)?,
+                None => writeln!(f, "{}", stage.query)?,
+            }
+            writeln!(f, "  request  GET {}", stage.url)?;
+            if stage.local.is_empty() {
+                writeln!(f, "  local    nothing")?;
+            }
+            for (index, operation) in stage.local.iter().enumerate() {
+                writeln!(f, "  local    {}. {}", index + 1, operation)?;
+            }
+            for diagnostic in &stage.diagnostics {
+                match diagnostic.severity {
+                    Severity::Error => writeln!(f, "  error    {}", diagnostic)?,
+                    Severity::Warning => writeln!(f, "  warning  {}", diagnostic)?,
+                }
+            }
+        }
+        match self.page_fetches() {
+            (fewest, most) if fewest == most => write!(f, "estimated page fetches: {}", most),
+            (fewest, most) => write!(f, "estimated page fetches: {} to {}", fewest, most),
+        }
+    }
+}
+
+/// Explains a query, or a plan of several, as it would run against `base_url`.
+pub fn explain(base_url: &str, plan: &Plan) -> Result<Explanation, RickError> {
+    let mut stages = vec![];
+    for (binding, pipeline) in plan.pipelines()? {
+        for (index, stage) in pipeline.iter().enumerate() {
+            stages.push(explain_stage(
+                base_url,
+                binding.map(str::to_string),
+                stage,
+                index > 0,
+            )?);
+        }
+    }
+    Ok(Explanation { stages })
+}
+
+fn explain_stage(
+    base_url: &str,
+    binding: Option<String>,
+    stage: &str,
+    piped: bool,
+) -> Result<StageExplanation, RickError> {
+    root_of(stage)?;
+    let (query, stand_ins) = with_stand_ins(stage, piped)?;
+    let (operation_list, mut diagnostics) = parse_and_validate(&query);
+    let (_, mut url) = build_url_from_operations(base_url, &operation_list)?;
+    for (stand_in, meaning) in &stand_ins {
+        url = url.replace(stand_in, &format!("{{{}}}", meaning));
+    }
+    let ids_may_be_empty = !stand_ins.is_empty();
+    let (local, notes) = local_operations(&operation_list);
+    diagnostics.extend(notes);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    Ok(StageExplanation {
+        binding,
+        query: stage.to_string(),
+        url,
+        local,
+        diagnostics,
+        ids_may_be_empty,
+    })
+}
+
+/// The operations applied to the fetched page, mirroring
+/// `RMResponse::evaluate_op`, and a warning for a SORT that cannot see every
+/// result.
+fn local_operations(operation_list: &OperationList) -> (Vec<String>, Vec<Diagnostic>) {
+    let selects_ids = operation_list
+        .0
+        .iter()
+        .any(|operation| matches!(operation.0, OperationEnum::Ids(_)));
+    let mut local = vec![];
+    let mut notes = vec![];
+    for (position, operation) in operation_list.0.iter().enumerate() {
+        let text = String::from(operation);
+        match &operation.0 {
+            OperationEnum::Sort(_, _) if !selects_ids => {
+                notes.push(Diagnostic {
+                    severity: Severity::Warning,
+                    position,
+                    message: format!("`{}` orders the fetched page only, not every result", text),
+                    operation: text.clone(),
+                });
+                local.push(text);
+            }
+            OperationEnum::Contains(_, _) | OperationEnum::Sort(_, _) | OperationEnum::Limit(_) => {
+                local.push(text)
+            }
+            _ => {}
+        }
+    }
+    (local, notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::DEFAULT_BASE_URL;
+
+    #[test]
+    fn explains_server_and_local_operations() {
+        let explanation = explain(
+            DEFAULT_BASE_URL,
+            &Plan::parse(
+                "CHARACTERS::NAME(morty)::DIMENSION(C-137)::CONTAINS(status, ive)::LIMIT(3)::NAME(rick)",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(explanation.page_fetches(), (1, 1));
+        let stage = &explanation.stages[0];
+        assert_eq!(
+            stage.url,
+            "https://rickandmortyapi.com/api/character/?name=rick"
+        );
+        assert_eq!(stage.local, vec!["CONTAINS(status, ive)", "LIMIT(3)"]);
+        let diagnostics: Vec<(Severity, &str)> = stage
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    Severity::Warning,
+                    "`NAME(morty)` is replaced by a later one"
+                ),
+                (
+                    Severity::Error,
+                    "`DIMENSION(C-137)` only applies to LOCATIONS"
+                ),
+            ]
+        );
+        assert!(explanation
+            .to_string()
+            .contains("  error    `DIMENSION(C-137)` only applies to LOCATIONS\n"));
+    }
+
+    #[test]
+    fn explains_plans() {
+        let explanation = explain(
+            DEFAULT_BASE_URL,
+            &Plan::parse(
+                "LET pilot = EPISODES::NAME(Pilot); CHARACTERS::IDS($pilot.characters)::SORT(ASC, name) | EPISODES",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        // the characters and episodes are not fetched when the pilot has no characters
+        assert_eq!(explanation.page_fetches(), (1, 3));
+        let urls: Vec<&str> = explanation
+            .stages
+            .iter()
+            .map(|stage| stage.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://rickandmortyapi.com/api/episode/?name=Pilot",
+                "https://rickandmortyapi.com/api/character/{$pilot.characters}?",
+                "https://rickandmortyapi.com/api/episode/{piped ids}?",
+            ]
+        );
+        assert_eq!(explanation.stages[0].binding.as_deref(), Some("pilot"));
+        // sorting results selected by id sees all of them
+        assert!(explanation.stages[1].diagnostics.is_empty());
+
+        let printed = explanation.to_string();
+        assert!(printed.starts_with("$pilot = EPISODES::NAME(Pilot)\n  request  GET "));
+        assert!(printed.contains("  local    1. SORT(ASC, name)\n"));
+        assert!(printed.ends_with("estimated page fetches: 1 to 3"));
+        assert!(explain(DEFAULT_BASE_URL, &Plan::parse("NAME(rick)").unwrap()).is_err());
+    }
+}
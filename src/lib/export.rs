@@ -0,0 +1,125 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use super::{
+    error::RickError,
+    rm_api::{entities::Entity, response::RMResponseEnum},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = RickError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(RickError::Parse(format!(
+                "unknown export format `{}`, expected csv or json",
+                format
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Renders the results of a response, returning the text and the number of rows.
+pub fn export(
+    response: &RMResponseEnum,
+    format: ExportFormat,
+) -> Result<(String, usize), RickError> {
+    match response {
+        RMResponseEnum::Characters(page) => export_results(&page.results, format),
+        RMResponseEnum::Episodes(page) => export_results(&page.results, format),
+        RMResponseEnum::Locations(page) => export_results(&page.results, format),
+    }
+}
+
+pub fn export_to_file(
+    response: &RMResponseEnum,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, RickError> {
+    let (contents, rows) = export(response, format)?;
+    fs::write(path, contents)
+        .map_err(|err| RickError::Io(format!("could not write {} ({})", path.display(), err)))?;
+    Ok(rows)
+}
+
+fn export_results<T: Entity + serde::Serialize>(
+    results: &[T],
+    format: ExportFormat,
+) -> Result<(String, usize), RickError> {
+    let contents = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(results)?,
+        ExportFormat::Csv => {
+            let mut lines = vec![T::FIELD_NAMES.join(",")];
+            for result in results {
+                let row = T::FIELD_NAMES
+                    .iter()
+                    .map(|name| csv_escape(&result.field(name).unwrap_or_default()))
+                    .collect::<Vec<_>>();
+                lines.push(row.join(","));
+            }
+            lines.join("\n") + "\n"
+        }
+    };
+    Ok((contents, results.len()))
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::entities::*;
+
+    #[test]
+    fn exports_csv() {
+        let response = RMResponseEnum::Episodes(EpisodePage {
+            results: vec![Episode {
+                id: 1,
+                name: "Pilot".into(),
+                air_date: "December 2, 2013".into(),
+                episode: "S01E01".into(),
+                characters: vec![
+                    "https://rickandmortyapi.com/api/character/1".into(),
+                    "https://rickandmortyapi.com/api/character/2".into(),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+
+        let (contents, rows) = export(&response, ExportFormat::Csv).unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(
+            contents,
+            "id,name,air_date,episode,characters,url,created\n1,Pilot,\"December 2, 2013\",S01E01,\"1,2\",,\n"
+        );
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("CSV".parse::<ExportFormat>().unwrap(), ExportFormat::Csv);
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
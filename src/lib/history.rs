@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::RickError;
+
+const HISTORY_FILE: &str = "history.jsonl";
+/// Older entries are dropped once the history grows past this
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub query: String,
+    pub timestamp: DateTime<Utc>,
+    /// The number of results on the page, `None` when the query failed
+    pub result_count: Option<usize>,
+    pub duration_ms: u64,
+}
+
+/// Every query that was run, oldest first, appended to a JSON lines file so
+/// it survives restarts.
+#[derive(Default, Debug)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// A history that is never written to disk
+    pub fn in_memory() -> Self {
+        History::default()
+    }
+
+    /// Reads the history in `dir`, starting empty when there is none yet.
+    /// Lines that cannot be read, e.g from a crash mid-write, are skipped.
+    pub fn load(dir: &Path) -> Result<Self, RickError> {
+        let path = dir.join(HISTORY_FILE);
+        let mut entries: Vec<HistoryEntry> = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+
+        let truncated = entries.len() > MAX_ENTRIES;
+        if truncated {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+        let history = History {
+            entries,
+            path: Some(path),
+        };
+        if truncated {
+            history.rewrite()?;
+        }
+        Ok(history)
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds an entry and appends it to the file, if there is one.
+    pub fn record(&mut self, entry: HistoryEntry) -> Result<(), RickError> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Finds the newest entry before `before` whose query contains `needle`,
+    /// searching the whole history when `before` is `None`.
+    pub fn search(&self, needle: &str, before: Option<usize>) -> Option<usize> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .rposition(|entry| entry.query.contains(needle))
+    }
+
+    fn rewrite(&self) -> Result<(), RickError> {
+        if let Some(path) = &self.path {
+            let mut contents = String::new();
+            for entry in &self.entries {
+                contents.push_str(&serde_json::to_string(entry)?);
+                contents.push('\n');
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query: &str) -> HistoryEntry {
+        HistoryEntry {
+            query: query.into(),
+            timestamp: Utc::now(),
+            result_count: Some(1),
+            duration_ms: 10,
+        }
+    }
+
+    #[test]
+    fn persists_across_loads() {
+        let dir = std::env::temp_dir().join(format!("rick_cli_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut history = History::load(&dir).unwrap();
+        assert!(history.is_empty());
+        history.record(entry("CHARACTERS::NAME(rick)")).unwrap();
+        history.record(entry("EPISODES")).unwrap();
+
+        let reloaded = History::load(&dir).unwrap();
+        assert_eq!(reloaded.entries(), history.entries());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn searches_backwards() {
+        let mut history = History::in_memory();
+        for query in [
+            "CHARACTERS::NAME(rick)",
+            "EPISODES",
+            "CHARACTERS::NAME(morty)",
+        ] {
+            history.record(entry(query)).unwrap();
+        }
+
+        assert_eq!(history.search("CHARACTERS", None), Some(2));
+        assert_eq!(history.search("CHARACTERS", Some(2)), Some(0));
+        assert_eq!(history.search("CHARACTERS", Some(0)), None);
+        assert_eq!(history.search("LOCATIONS", None), None);
+    }
+}
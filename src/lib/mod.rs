@@ -1,30 +1,77 @@
-use std::error::Error;
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
 
 use self::{
-    query_language::operation_list::{OperationList, OperationListEvaluator},
-    rm_api::response::RMResponseEnum,
+    error::RickError,
+    query_language::{
+        operation_list::OperationListEvaluator,
+        validate::{errors, parse_and_validate},
+    },
+    rm_api::{request::transport::CACHE_HITS, response::RMResponseEnum},
 };
 
+pub mod error;
+pub mod explain;
+pub mod export;
+pub mod history;
+pub mod planner;
 pub mod query_language;
 pub mod rm_api;
+pub mod script;
+
 
+/// How a query went, for the status bar.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryStats {
+    /// Results the server returned, before the operations applied locally
+    pub fetched: usize,
+    /// Results left after the local operations
+    pub shown: usize,
+    /// Whether any response was served from the cache instead of the network
+    pub from_cache: bool,
+    pub elapsed: Duration,
+}
 
-pub async fn query_api<T: OperationListEvaluator>(
+/// Parses, validates and runs a query, calling `progress` as each stage starts.
+pub async fn query_api<T: OperationListEvaluator, F: Fn(&str) + Send + Sync>(
     request: T,
     input: &str,
-) -> Result<RMResponseEnum, Box<dyn Error>> {
-    let operation_list = OperationList::parse_str(input)?;
-    let response = request.evaluate_op(&operation_list).await?;
-    let evaluated_response = response.evaluate_op(&operation_list).await?.0;
-    Ok(evaluated_response)
+    progress: F,
+) -> Result<(RMResponseEnum, QueryStats), RickError> {
+    let started_at = Instant::now();
+    CACHE_HITS
+        .scope(Cell::new(0), async {
+            progress("parsing query");
+            let (operation_list, diagnostics) = parse_and_validate(input);
+            let errors = errors(diagnostics);
+            if !errors.is_empty() {
+                return Err(RickError::Invalid(errors));
+            }
+            progress("waiting for the server");
+            let response = request.evaluate_op(&operation_list).await?;
+            let fetched = response.0.result_count();
+            progress("applying local operations");
+            let evaluated_response = response.evaluate_op(&operation_list).await?.0;
+            let stats = QueryStats {
+                fetched,
+                shown: evaluated_response.result_count(),
+                from_cache: CACHE_HITS.with(Cell::get) > 0,
+                elapsed: started_at.elapsed(),
+            };
+            Ok((evaluated_response, stats))
+        })
+        .await
 }
 
 // tests
 #[cfg(test)]
 mod tests {
-  use tokio;
 
     use crate::lib::{
+        error::RickError,
+        query_api,
         query_language::operation_list::*,
         rm_api::{request::mock_request::MockRequest, response::RMResponseEnum},
     };
@@ -51,10 +98,67 @@ mod tests {
 
         match evaluated_response {
             RMResponseEnum::Characters(page) => {
-                assert_eq!(page.results.len(), 1)
+                assert_eq!(page.results.len(), 4)
             }
             RMResponseEnum::Episodes(_) => panic!(),
             RMResponseEnum::Locations(_) => panic!(),
         }
     }
+
+    #[tokio::test]
+    async fn sorts_by_created_dates() {
+        for (query, root) in [
+            ("CHARACTERS::NAME(rick)::SORT(ASC, created)::LIMIT(10)", "characters"),
+            ("EPISODES::NAME(a)::SORT(DSC, created)::LIMIT(10)", "episodes"),
+        ] {
+            let (response, _) = query_api(MockRequest, query, |_| {}).await.unwrap();
+            let created: Vec<String> = match response {
+                RMResponseEnum::Characters(page) => page.results.into_iter().map(|c| c.created).collect(),
+                RMResponseEnum::Episodes(page) => page.results.into_iter().rev().map(|e| e.created).collect(),
+                RMResponseEnum::Locations(_) => panic!(),
+            };
+            assert!(created.len() > 1, "too few {} to compare", root);
+            // the fixtures' dates are all UTC, so they order as strings do
+            assert!(created.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", created);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_queries_before_requesting() {
+        let error = query_api(
+            MockRequest,
+            "EPISODES::DIMENSION(C-137)::SORT(ASC, height)",
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        match &error {
+            RickError::Invalid(diagnostics) => {
+                let positions: Vec<usize> = diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.position)
+                    .collect();
+                assert_eq!(positions, vec![1, 2]);
+            }
+            _ => panic!("expected an invalid query, got {:?}", error),
+        }
+        assert_eq!(error.kind(), "Invalid query");
+        assert!(error
+            .to_string()
+            .starts_with("`DIMENSION(C-137)` only applies to LOCATIONS; `SORT(ASC, height)`"));
+        // warnings alone do not stop a query
+        assert!(
+            query_api(MockRequest, "CHARACTERS::NAME(morty)::NAME(rick)", |_| {})
+                .await
+                .is_ok()
+        );
+        // an operation that does not parse is reported with the others
+        let error = query_api(MockRequest, "EPISODES::NAEM(rick)::DIMENSION(C-137)", |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Operation not found: `NAEM(rick)`; `DIMENSION(C-137)` only applies to LOCATIONS"
+        );
+    }
 }
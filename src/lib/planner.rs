@@ -0,0 +1,588 @@
+use std::{collections::HashMap, fmt, ops::Range, time::Instant};
+
+use super::{
+    error::RickError,
+    query_api,
+    query_language::{
+        macros::Macros,
+        operand::{Operand, OperandEnum},
+        operation::{Operation, OperationEnum, Root},
+        operation_list::{OperationList, OperationListEvaluator},
+        split_top_level,
+    },
+    rm_api::{entities::Entity, response::RMResponseEnum},
+    QueryStats,
+};
+
+/// The fields holding lists, whose values are taken one by one
+const LIST_FIELDS: &[&str] = &["episode", "characters", "residents"];
+
+/// A query that may bind results with `LET name = query;`, use them with
+/// `$name.field` and feed results into the next query with `|`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    /// The `LET` statements, each a pipeline of queries
+    bindings: Vec<(String, Vec<String>)>,
+    /// The pipeline whose result is shown
+    shown: Vec<String>,
+}
+
+/// The bindings, then the shown pipeline, as `Plan::parse` reads them
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, stages) in &self.bindings {
+            write!(f, "LET {} = {}; ", name, stages.join(" | "))?;
+        }
+        write!(f, "{}", self.shown.join(" | "))
+    }
+}
+
+/// A `$name` or `$name.field` in a query, the field being `id` when left out
+#[derive(Debug, Clone, PartialEq)]
+struct Reference {
+    range: Range<usize>,
+    name: String,
+    field: String,
+}
+
+/// Results bound by `LET`, by name
+pub type Bindings = HashMap<String, RMResponseEnum>;
+
+/// The name a pipeline is bound to, `None` for the shown one, and its queries
+pub type Pipeline<'a> = (Option<&'a str>, &'a [String]);
+
+/// One statement of a plan or a script, each a pipeline of queries.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let(String, Vec<String>),
+    Query(Vec<String>),
+}
+
+impl Statement {
+    /// Reads `LET name = query | query` or `query | query`, replacing every
+    /// `@name(args)` operation with the operations saved under that name.
+    pub fn parse(statement: &str, macros: &Macros) -> Result<Self, RickError> {
+        let statement = statement.trim();
+        let Some(binding) = statement.strip_prefix("LET ") else {
+            return Ok(Statement::Query(stages(statement, macros)?));
+        };
+        let (name, query) = binding.split_once('=').ok_or_else(|| {
+            RickError::Parse(format!("expected `LET name = query` in `{}`", statement))
+        })?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(RickError::Parse(format!(
+                "`{}` is not a variable name",
+                name
+            )));
+        }
+        Ok(Statement::Let(name.to_string(), stages(query, macros)?))
+    }
+}
+
+impl Plan {
+    /// Reads a plan without macros, e.g one already expanded.
+    pub fn parse(input: &str) -> Result<Self, RickError> {
+        Plan::parse_with(input, &Macros::in_memory())
+    }
+
+    /// Reads `;` separated statements, all but one of them `LET` bindings.
+    /// A `;` inside an operation's parentheses is part of its operand.
+    /// Macros are expanded here, so everything reading the plan sees the
+    /// operations they stand for.
+    pub fn parse_with(input: &str, macros: &Macros) -> Result<Self, RickError> {
+        let mut bindings: Vec<(String, Vec<String>)> = vec![];
+        let mut shown = vec![];
+        for statement in split_top_level(input, ";")
+            .into_iter()
+            .filter(|s| !s.trim().is_empty())
+        {
+            match Statement::parse(statement, macros)? {
+                Statement::Query(stages) => shown.push(stages),
+                Statement::Let(name, _) if bindings.iter().any(|(bound, _)| *bound == name) => {
+                    return Err(RickError::Parse(format!("`${}` is bound twice", name)));
+                }
+                Statement::Let(name, stages) => bindings.push((name, stages)),
+            }
+        }
+        let shown = match shown.len() {
+            0 => {
+                return Err(RickError::Parse(
+                    "nothing to show, end with a query that is not a LET".to_string(),
+                ))
+            }
+            1 => shown.remove(0),
+            _ => {
+                return Err(RickError::Parse(
+                    "only one query can be shown, bind the others with LET".to_string(),
+                ))
+            }
+        };
+        Ok(Plan { bindings, shown })
+    }
+
+    /// Whether this is one plain query, with nothing to bind or pipe.
+    pub fn is_single_query(&self) -> bool {
+        self.bindings.is_empty() && self.shown.len() == 1 && references(&self.shown[0]).is_empty()
+    }
+
+    /// A copy of the plan whose last query, the one giving the result shown,
+    /// ends in `LIMIT(limit)` unless it has a LIMIT already.
+    pub fn with_default_limit(&self, limit: u32) -> Result<Plan, RickError> {
+        self.with_last_stage(|operation_list| operation_list.with_default_limit(limit))
+    }
+
+    /// The page of its last query that the plan shows.
+    pub fn page(&self) -> Result<u32, RickError> {
+        let Some(last) = self.shown.last() else {
+            return Ok(1);
+        };
+        let (query, _) = with_stand_ins(last, false)?;
+        Ok(OperationList::parse_str(&query)?.page())
+    }
+
+    /// A copy of the plan showing `page` of its last query.
+    pub fn with_page(&self, page: u32) -> Result<Plan, RickError> {
+        self.with_last_stage(|operation_list| operation_list.with_page(page))
+    }
+
+    /// A copy of the plan with `change` made to the operations of its last
+    /// query, the references in it kept as they are.
+    fn with_last_stage<F>(&self, change: F) -> Result<Plan, RickError>
+    where
+        F: FnOnce(&OperationList) -> OperationList,
+    {
+        let mut plan = self.clone();
+        let Some(last) = plan.shown.last_mut() else {
+            return Ok(plan);
+        };
+        let (query, stand_ins) = with_stand_ins(last, false)?;
+        let mut changed = change(&OperationList::parse_str(&query)?).to_string();
+        for (stand_in, meaning) in &stand_ins {
+            changed = changed.replace(stand_in, meaning);
+        }
+        *last = changed;
+        Ok(plan)
+    }
+
+    /// The pipelines that run, in order: the bindings the shown pipeline
+    /// needs, each after the ones it uses, then the shown one without a name.
+    /// Bindings nothing uses are left out.
+    pub fn pipelines(&self) -> Result<Vec<Pipeline<'_>>, RickError> {
+        let mut pipelines: Vec<Pipeline> = self
+            .order()?
+            .into_iter()
+            .map(|index| {
+                let (name, stages) = &self.bindings[index];
+                (Some(name.as_str()), stages.as_slice())
+            })
+            .collect();
+        pipelines.push((None, &self.shown));
+        Ok(pipelines)
+    }
+
+    fn order(&self) -> Result<Vec<usize>, RickError> {
+        let mut order = vec![];
+        let mut visiting = vec![];
+        for reference in self.shown.iter().flat_map(|stage| references(stage)) {
+            self.visit(&reference.name, &mut visiting, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), RickError> {
+        let index = self
+            .bindings
+            .iter()
+            .position(|(bound, _)| bound == name)
+            .ok_or_else(|| RickError::Parse(format!("unknown variable `${}`", name)))?;
+        if order.contains(&index) {
+            return Ok(());
+        }
+        if visiting.iter().any(|visited| visited == name) {
+            return Err(RickError::Parse(format!("`${}` depends on itself", name)));
+        }
+        visiting.push(name.to_string());
+        for reference in self.bindings[index]
+            .1
+            .iter()
+            .flat_map(|stage| references(stage))
+        {
+            self.visit(&reference.name, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(index);
+        Ok(())
+    }
+}
+
+fn stages(pipeline: &str, macros: &Macros) -> Result<Vec<String>, RickError> {
+    split_top_level(pipeline, "|")
+        .into_iter()
+        .map(|stage| match stage.trim() {
+            "" => Err(RickError::Parse(format!(
+                "empty query in `{}`",
+                pipeline.trim()
+            ))),
+            stage => macros.expand(stage),
+        })
+        .collect()
+}
+
+fn references(query: &str) -> Vec<Reference> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let word_end = |from: usize| {
+        query[from..]
+            .find(|c: char| !is_word(c))
+            .map(|end| from + end)
+            .unwrap_or(query.len())
+    };
+    let mut found = vec![];
+    for (start, _) in query.match_indices('$') {
+        let name_end = word_end(start + 1);
+        if name_end == start + 1 {
+            continue;
+        }
+        let (field, end) = match query[name_end..].strip_prefix('.') {
+            Some(_) if word_end(name_end + 1) > name_end + 1 => {
+                let end = word_end(name_end + 1);
+                (query[name_end + 1..end].to_string(), end)
+            }
+            _ => ("id".to_string(), name_end),
+        };
+        found.push(Reference {
+            range: start..end,
+            name: query[start + 1..name_end].to_string(),
+            field,
+        });
+    }
+    found
+}
+
+/// Runs a query, or a plan of several, as `query_api` does for one.
+pub async fn query_plan<T, F>(
+    request: T,
+    plan: &Plan,
+    progress: F,
+) -> Result<(RMResponseEnum, QueryStats), RickError>
+where
+    T: OperationListEvaluator + Clone + Send + Sync,
+    F: Fn(&str) + Send + Sync,
+{
+    if plan.is_single_query() {
+        return query_api(request, &plan.shown[0], progress).await;
+    }
+    let started_at = Instant::now();
+    let mut results = Bindings::new();
+    for (name, stages) in plan.pipelines()? {
+        let Some(name) = name else {
+            let (response, stats) = run_pipeline(&request, stages, &results, &progress).await?;
+            return Ok((
+                response,
+                QueryStats {
+                    elapsed: started_at.elapsed(),
+                    ..stats
+                },
+            ));
+        };
+        let step = |stage: &str| progress(&format!("${}: {}", name, stage));
+        let (response, _) = run_pipeline(&request, stages, &results, &step).await?;
+        results.insert(name.to_string(), response);
+    }
+    unreachable!("the shown pipeline comes last")
+}
+
+/// Runs each query of a pipeline with the ids of the previous one's results.
+pub async fn run_pipeline<T, F>(
+    request: &T,
+    stages: &[String],
+    results: &Bindings,
+    progress: &F,
+) -> Result<(RMResponseEnum, QueryStats), RickError>
+where
+    T: OperationListEvaluator + Clone + Send + Sync,
+    F: Fn(&str) + Send + Sync,
+{
+    let mut previous: Option<(RMResponseEnum, QueryStats)> = None;
+    for stage in stages {
+        let root = root_of(stage)?;
+        let ids = match &previous {
+            Some((response, _)) => Some(piped_ids(response, &root)?),
+            None => None,
+        };
+        // a query asking for the ids of nothing finds nothing, without asking the server
+        let query = match (substitute(stage, results)?, ids) {
+            (Some(query), None) => Some(query),
+            (Some(query), Some(ids)) if !ids.is_empty() => Some(with_ids(&query, &ids)?),
+            _ => None,
+        };
+        previous = Some(match query {
+            Some(query) => query_api(request.clone(), &query, progress).await?,
+            None => (empty(&root), QueryStats::default()),
+        });
+    }
+    previous.ok_or_else(|| RickError::Parse("empty pipeline".to_string()))
+}
+
+/// Ids that no result has, standing in for the values of references and
+/// piped ids while explaining a query
+const STAND_IN_IDS: u32 = 16_000_000;
+
+/// `stage` with stand in ids for the values it is only given once the
+/// queries before it ran, the ids piped into it too when `piped`. Returns
+/// the query and what each stand in, as text, stands for.
+pub fn with_stand_ins(
+    stage: &str,
+    piped: bool,
+) -> Result<(String, Vec<(String, String)>), RickError> {
+    let mut query = stage.to_string();
+    let mut stand_ins = vec![];
+    for (index, reference) in references(stage).into_iter().enumerate().rev() {
+        let stand_in = (STAND_IN_IDS + 1 + index as u32).to_string();
+        query.replace_range(reference.range, &stand_in);
+        stand_ins.push((stand_in, format!("${}.{}", reference.name, reference.field)));
+    }
+    if piped {
+        let stand_in = STAND_IN_IDS.to_string();
+        query = with_ids(&query, std::slice::from_ref(&stand_in))?;
+        stand_ins.push((stand_in, "piped ids".to_string()));
+    }
+    Ok((query, stand_ins))
+}
+
+pub fn root_of(query: &str) -> Result<Root, RickError> {
+    let first = split_top_level(query, "::")[0].trim();
+    match Operation::parse_str(first) {
+        Ok(Operation(OperationEnum::Root(root))) => Ok(root),
+        _ => Err(RickError::Parse(format!(
+            "`{}` must start with CHARACTERS, EPISODES or LOCATIONS",
+            query
+        ))),
+    }
+}
+
+/// Replaces the references in `query` with the values they point to,
+/// `None` when one of them points to nothing.
+fn substitute(query: &str, results: &Bindings) -> Result<Option<String>, RickError> {
+    let mut substituted = query.to_string();
+    for reference in references(query).into_iter().rev() {
+        let response = results
+            .get(&reference.name)
+            .ok_or_else(|| RickError::Parse(format!("unknown variable `${}`", reference.name)))?;
+        let values = field_values(response, &reference.field)?;
+        if values.is_empty() {
+            return Ok(None);
+        }
+        substituted.replace_range(reference.range, &values.join(", "));
+    }
+    Ok(Some(substituted))
+}
+
+/// The ids a result feeds into a query for `root`: the results' own ids for
+/// the same root, otherwise the list pointing to `root`.
+fn piped_ids(response: &RMResponseEnum, root: &Root) -> Result<Vec<String>, RickError> {
+    let field = match (response, root) {
+        (RMResponseEnum::Characters(_), Root::CHARACTERS)
+        | (RMResponseEnum::Episodes(_), Root::EPISODES)
+        | (RMResponseEnum::Locations(_), Root::LOCATIONS) => "id",
+        (RMResponseEnum::Episodes(_), Root::CHARACTERS) => "characters",
+        (RMResponseEnum::Locations(_), Root::CHARACTERS) => "residents",
+        (RMResponseEnum::Characters(_), Root::EPISODES) => "episode",
+        _ => {
+            return Err(RickError::Parse(format!(
+                "cannot pipe {:?} into {:?}",
+                root_of_response(response),
+                root
+            )))
+        }
+    };
+    field_values(response, field)
+}
+
+fn root_of_response(response: &RMResponseEnum) -> Root {
+    match response {
+        RMResponseEnum::Characters(_) => Root::CHARACTERS,
+        RMResponseEnum::Episodes(_) => Root::EPISODES,
+        RMResponseEnum::Locations(_) => Root::LOCATIONS,
+    }
+}
+
+fn field_values(response: &RMResponseEnum, field: &str) -> Result<Vec<String>, RickError> {
+    match response {
+        RMResponseEnum::Characters(page) => values(&page.results, "characters", field),
+        RMResponseEnum::Episodes(page) => values(&page.results, "episodes", field),
+        RMResponseEnum::Locations(page) => values(&page.results, "locations", field),
+    }
+}
+
+/// A field of every result, lists taken apart, without repeats.
+fn values<T: Entity>(results: &[T], entity: &str, field: &str) -> Result<Vec<String>, RickError> {
+    if !T::FIELD_NAMES.contains(&field) {
+        return Err(RickError::UnknownField {
+            entity: entity.to_string(),
+            field: field.to_string(),
+        });
+    }
+    let mut values: Vec<String> = vec![];
+    for result in results {
+        let value = result.field(field).unwrap_or_default();
+        let parts = if LIST_FIELDS.contains(&field) {
+            value.split(',').map(str::to_string).collect()
+        } else {
+            vec![value]
+        };
+        for part in parts {
+            if !part.is_empty() && !values.contains(&part) {
+                values.push(part);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// `query` selecting `ids`, in place of any ids it selected before.
+fn with_ids(query: &str, ids: &[String]) -> Result<String, RickError> {
+    let ids = ids
+        .iter()
+        .map(|id| {
+            id.parse::<u32>()
+                .map(|id| id as f32)
+                .map_err(|_| RickError::Parse(format!("`{}` is not an id", id)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut operation_list = OperationList::parse_str(query)?;
+    operation_list
+        .0
+        .retain(|operation| !matches!(operation.0, OperationEnum::Ids(_)));
+    let position = operation_list.len().min(1);
+    operation_list.0.insert(
+        position,
+        Operation(OperationEnum::Ids(Operand(OperandEnum::NumberList(ids)))),
+    );
+    Ok(operation_list.to_string())
+}
+
+fn empty(root: &Root) -> RMResponseEnum {
+    match root {
+        Root::CHARACTERS => RMResponseEnum::Characters(Default::default()),
+        Root::EPISODES => RMResponseEnum::Episodes(Default::default()),
+        Root::LOCATIONS => RMResponseEnum::Locations(Default::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::request::mock_request::MockRequest;
+
+    async fn run(input: &str) -> Result<RMResponseEnum, RickError> {
+        query_plan(MockRequest, &Plan::parse(input)?, |_| {})
+            .await
+            .map(|(response, _)| response)
+    }
+
+    fn names(response: RMResponseEnum) -> Vec<String> {
+        match response {
+            RMResponseEnum::Characters(page) => page.results.into_iter().map(|c| c.name).collect(),
+            RMResponseEnum::Episodes(page) => page.results.into_iter().map(|e| e.name).collect(),
+            RMResponseEnum::Locations(page) => page.results.into_iter().map(|l| l.name).collect(),
+        }
+    }
+
+    #[test]
+    fn orders_bindings_by_dependency() {
+        let plan = Plan::parse(
+            "LET b = CHARACTERS::IDS($a.characters); LET unused = EPISODES; \
+             LET a = EPISODES::NAME(Pilot); EPISODES::IDS($b.episode)",
+        )
+        .unwrap();
+        assert_eq!(plan.order().unwrap(), vec![2, 0]);
+        assert!(!plan.is_single_query());
+        assert!(Plan::parse("CHARACTERS::NAME(rick)")
+            .unwrap()
+            .is_single_query());
+
+        let cycle =
+            Plan::parse("LET a = CHARACTERS::IDS($b); LET b = CHARACTERS::IDS($a); $a").unwrap();
+        assert!(cycle
+            .order()
+            .unwrap_err()
+            .to_string()
+            .contains("depends on itself"));
+        assert!(Plan::parse("LET a = EPISODES").is_err());
+        assert!(Plan::parse("EPISODES; CHARACTERS").is_err());
+        assert!(Plan::parse("EPISODES | ").is_err());
+        assert!(Plan::parse("CHARACTERS::IDS($nope)")
+            .unwrap()
+            .order()
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_separators_inside_operands() {
+        let plan = Plan::parse("CHARACTERS::NAME(a|b)").unwrap();
+        assert_eq!(plan.shown, vec!["CHARACTERS::NAME(a|b)".to_string()]);
+        assert!(plan.is_single_query());
+
+        let plan = Plan::parse(
+            "LET x = CHARACTERS::CONTAINS(name, x;y); EPISODES::IDS($x.episode) | CHARACTERS::NAME(a|b)",
+        )
+        .unwrap();
+        assert_eq!(
+            plan.bindings,
+            vec![(
+                "x".to_string(),
+                vec!["CHARACTERS::CONTAINS(name, x;y)".to_string()]
+            )]
+        );
+        assert_eq!(
+            plan.shown,
+            vec![
+                "EPISODES::IDS($x.episode)".to_string(),
+                "CHARACTERS::NAME(a|b)".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_references() {
+        let found = references("CHARACTERS::IDS($pilot.characters, $ricks)::NAME($)");
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            (found[0].name.as_str(), found[0].field.as_str()),
+            ("pilot", "characters")
+        );
+        assert_eq!(
+            (found[1].name.as_str(), found[1].field.as_str()),
+            ("ricks", "id")
+        );
+    }
+
+    #[tokio::test]
+    async fn pipes_results_into_the_next_query() {
+        let piped = run("EPISODES::NAME(Pilot) | CHARACTERS::LIMIT(2)")
+            .await
+            .unwrap();
+        let bound =
+            run("LET pilot = EPISODES::NAME(Pilot); CHARACTERS::IDS($pilot.characters)::LIMIT(2)")
+                .await
+                .unwrap();
+        assert_eq!(names(piped.clone()), vec!["Rick Sanchez", "Morty Smith"]);
+        assert_eq!(piped, bound);
+
+        // nothing to feed in finds nothing
+        let nothing = run("EPISODES::NAME(xxxxxx) | CHARACTERS").await.unwrap();
+        assert!(names(nothing).is_empty());
+
+        assert!(matches!(
+            run("LET e = EPISODES; CHARACTERS::IDS($e.residents)").await,
+            Err(RickError::UnknownField { .. })
+        ));
+        assert!(run("EPISODES | LOCATIONS").await.is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use super::{
+    fields::Fields,
+    operation::{Operation, OperationEnum},
+};
+
+pub const ROOTS: &[&str] = &["CHARACTERS", "EPISODES", "LOCATIONS"];
+
+/// Every operation that can follow the root, as it is typed before its operands
+pub const OPERATIONS: &[&str] = &[
+    "NAME(",
+    "PAGE(",
+    "DIMENSION(",
+    "CONTAINS(",
+    "LENGTH(",
+    "LIMIT(",
+    "SORT(",
+    "IDS(",
+];
+
+const SORT_DIRECTIONS: &[&str] = &["ASC", "DSC"];
+
+/// What the text between `start` and the cursor can be completed to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// Byte offset where the word being completed starts
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// Suggests roots, operations and the fields of the query's root for the
+/// word ending at `cursor`, a byte offset into `input`. Only the query being
+/// typed counts, not the statements, bindings or stages before it, nor an
+/// `EXPLAIN` in front of it.
+pub fn complete(input: &str, cursor: usize) -> Completion {
+    let before = &input[..cursor];
+    let start = before
+        .rfind([';', '|', '=', '\n'])
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    let start = start + before[start..].len() - before[start..].trim_start().len();
+    let start = match before[start..].strip_prefix("EXPLAIN ") {
+        Some(query) => cursor - query.trim_start().len(),
+        None => start,
+    };
+    let completion = complete_query(&before[start..]);
+    Completion {
+        start: start + completion.start,
+        ..completion
+    }
+}
+
+/// Completes the word at the end of `before`, a single query.
+fn complete_query(before: &str) -> Completion {
+    let segment_start = before.rfind("::").map(|index| index + 2).unwrap_or(0);
+    let segment = &before[segment_start..];
+
+    if segment_start == 0 {
+        return Completion {
+            start: 0,
+            candidates: matching(ROOTS, segment),
+        };
+    }
+
+    let Some(open) = segment.find('(') else {
+        return Completion {
+            start: segment_start,
+            candidates: matching(OPERATIONS, segment),
+        };
+    };
+    let operation = &segment[..open];
+    let operands = &segment[open + 1..];
+    let operand_index = operands.matches(',').count();
+    let word_offset = operands.rfind(',').map(|index| index + 1).unwrap_or(0);
+    let word_offset =
+        word_offset + operands[word_offset..].len() - operands[word_offset..].trim_start().len();
+    let start = segment_start + open + 1 + word_offset;
+    let word = &operands[word_offset..];
+
+    // fields depend on the root, which is the first segment
+    let fields = before
+        .split("::")
+        .next()
+        .and_then(|root| Operation::parse_str(root).ok())
+        .and_then(|operation| match operation.0 {
+            OperationEnum::Root(root) => Some(Fields::for_root(&root)),
+            _ => None,
+        });
+    let options: &[&str] = match (operation, operand_index, fields) {
+        ("CONTAINS" | "LENGTH", 0, Some(fields)) => fields.searchable,
+        ("SORT", 0, _) => SORT_DIRECTIONS,
+        ("SORT", 1, Some(fields)) => fields.sortable,
+        _ => &[],
+    };
+    Completion {
+        start,
+        candidates: matching(options, word),
+    }
+}
+
+fn matching(options: &[&str], prefix: &str) -> Vec<String> {
+    options
+        .iter()
+        .filter(|option| option.to_lowercase().starts_with(&prefix.to_lowercase()))
+        .map(|option| option.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(input: &str) -> Vec<String> {
+        complete(input, input.len()).candidates
+    }
+
+    #[test]
+    fn completes_roots_and_operations() {
+        assert_eq!(candidates("ep"), vec!["EPISODES"]);
+        assert_eq!(candidates("CHARACTERS::L"), vec!["LENGTH(", "LIMIT("]);
+        assert_eq!(complete("CHARACTERS::L", 13).start, 12);
+    }
+
+    #[test]
+    fn completes_fields_of_the_root() {
+        assert_eq!(candidates("EPISODES::CONTAINS(air"), vec!["air_date"]);
+        assert_eq!(candidates("LOCATIONS::SORT(ASC, dim"), vec!["dimension"]);
+        assert_eq!(candidates("LOCATIONS::SORT(D"), vec!["DSC"]);
+        assert_eq!(
+            complete("LOCATIONS::SORT(ASC, dim", 24).start,
+            "LOCATIONS::SORT(ASC, ".len()
+        );
+        assert!(candidates("CHARACTERS::CONTAINS(dimension").is_empty());
+    }
+
+    #[test]
+    fn completes_the_query_being_typed() {
+        assert_eq!(candidates("LET x = EP"), vec!["EPISODES"]);
+        assert_eq!(complete("LET x = EP", 10).start, 8);
+        assert_eq!(
+            candidates("CHARACTERS::NAME(Rick) | EPISODES::CONTAINS(air"),
+            vec!["air_date"]
+        );
+        assert_eq!(
+            candidates("LET e = EPISODES; CHARACTERS::L"),
+            vec!["LENGTH(", "LIMIT("]
+        );
+        assert_eq!(candidates("EXPLAIN CHARACTERS::SORT(ASC, na"), vec!["name"]);
+        assert_eq!(candidates("EXPLAIN  ch"), vec!["CHARACTERS"]);
+        assert_eq!(complete("EXPLAIN  ch", 11).start, 9);
+    }
+}
@@ -0,0 +1,58 @@
+use super::operation::Root;
+use crate::lib::error::RickError;
+
+/// The fields of an entity that operations can refer to.
+pub struct Fields {
+    pub entity: &'static str,
+    /// fields CONTAINS can match against
+    pub searchable: &'static [&'static str],
+    /// fields SORT can order by
+    pub sortable: &'static [&'static str],
+}
+
+pub const CHARACTER_FIELDS: Fields = Fields {
+    entity: "characters",
+    searchable: &["id", "name", "status", "species", "type", "gender"],
+    sortable: &["id", "name", "status", "species", "type", "created"],
+};
+
+pub const LOCATION_FIELDS: Fields = Fields {
+    entity: "locations",
+    searchable: &["id", "name", "type", "dimension"],
+    sortable: &["id", "name", "type", "dimension", "residents", "created"],
+};
+
+pub const EPISODE_FIELDS: Fields = Fields {
+    entity: "episodes",
+    searchable: &["id", "name", "air_date", "episode"],
+    sortable: &["id", "name", "air_date", "episode", "characters", "created"],
+};
+
+impl Fields {
+    pub fn for_root(root: &Root) -> &'static Fields {
+        match root {
+            Root::CHARACTERS => &CHARACTER_FIELDS,
+            Root::LOCATIONS => &LOCATION_FIELDS,
+            Root::EPISODES => &EPISODE_FIELDS,
+        }
+    }
+
+    pub fn check_searchable(&self, field: &str) -> Result<(), RickError> {
+        self.check(self.searchable, field)
+    }
+
+    pub fn check_sortable(&self, field: &str) -> Result<(), RickError> {
+        self.check(self.sortable, field)
+    }
+
+    fn check(&self, fields: &[&str], field: &str) -> Result<(), RickError> {
+        if fields.contains(&field) {
+            Ok(())
+        } else {
+            Err(RickError::UnknownField {
+                entity: self.entity.to_string(),
+                field: field.to_string(),
+            })
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::ops::Range;
+
+use super::{
+    operation::{Operation, OperationEnum},
+    split_top_level,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Root,
+    Operation,
+    Operand,
+    /// Parentheses and commas
+    Punctuation,
+    /// The `::` between operations
+    Separator,
+    /// A segment the parser rejects
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// Byte range in the highlighted input
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+
+/// Splits a query into tokens covering all of it, marking each segment
+/// between `::` that fails to parse as invalid. The `;` between statements
+/// and the `|` between the queries of a pipeline are separators too, and a
+/// leading `EXPLAIN` is an operation.
+pub fn highlight(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut offset = 0;
+    if input.starts_with("EXPLAIN ") {
+        tokens.push(token(0..7, TokenKind::Operation));
+        offset = 7;
+    }
+    for (index, statement) in split_top_level(&input[offset..], ";")
+        .into_iter()
+        .enumerate()
+    {
+        if index > 0 {
+            tokens.push(token(offset - 1..offset, TokenKind::Separator));
+        }
+        highlight_statement(statement, offset, &mut tokens);
+        offset += statement.len() + 1;
+    }
+    tokens
+}
+
+/// `LET name = query | query` -> operation, operand, punctuation, queries
+fn highlight_statement(statement: &str, offset: usize, tokens: &mut Vec<Token>) {
+    let leading = statement.len() - statement.trim_start().len();
+    let mut pipeline_start = 0;
+    if let (true, Some(equals)) = (
+        statement[leading..].starts_with("LET "),
+        statement.find('='),
+    ) {
+        if leading > 0 {
+            tokens.push(token(offset..offset + leading, TokenKind::Separator));
+        }
+        tokens.push(token(
+            offset + leading..offset + leading + 3,
+            TokenKind::Operation,
+        ));
+        tokens.push(token(
+            offset + leading + 3..offset + equals,
+            TokenKind::Operand,
+        ));
+        tokens.push(token(
+            offset + equals..offset + equals + 1,
+            TokenKind::Punctuation,
+        ));
+        pipeline_start = equals + 1;
+    }
+    let mut stage_offset = offset + pipeline_start;
+    for (index, stage) in split_top_level(&statement[pipeline_start..], "|")
+        .into_iter()
+        .enumerate()
+    {
+        if index > 0 {
+            tokens.push(token(stage_offset - 1..stage_offset, TokenKind::Separator));
+        }
+        highlight_query(stage, stage_offset, tokens);
+        stage_offset += stage.len() + 1;
+    }
+}
+
+/// The operations of a single query, the spaces around it taken as separators.
+fn highlight_query(query: &str, offset: usize, tokens: &mut Vec<Token>) {
+    let trimmed = query.trim_start();
+    let leading = query.len() - trimmed.len();
+    if leading > 0 {
+        tokens.push(token(offset..offset + leading, TokenKind::Separator));
+    }
+    let trimmed = trimmed.trim_end();
+    let mut segment_offset = offset + leading;
+    for (index, segment) in split_top_level(trimmed, "::").into_iter().enumerate() {
+        if index > 0 {
+            tokens.push(token(
+                segment_offset - 2..segment_offset,
+                TokenKind::Separator,
+            ));
+        }
+        if segment.starts_with('@') || segment.contains('$') {
+            // macros and variables are filled in before parsing, so they are taken on trust here
+            highlight_operation(segment, segment_offset, tokens);
+        } else if !segment.is_empty() {
+            match Operation::parse_str(segment) {
+                Ok(Operation(OperationEnum::Root(_))) => {
+                    tokens.push(token(
+                        segment_offset..segment_offset + segment.len(),
+                        TokenKind::Root,
+                    ));
+                }
+                Ok(_) => highlight_operation(segment, segment_offset, tokens),
+                Err(_) => tokens.push(token(
+                    segment_offset..segment_offset + segment.len(),
+                    TokenKind::Invalid,
+                )),
+            }
+        }
+        segment_offset += segment.len() + 2;
+    }
+    if offset + leading + trimmed.len() < offset + query.len() {
+        tokens.push(token(
+            offset + leading + trimmed.len()..offset + query.len(),
+            TokenKind::Separator,
+        ));
+    }
+}
+
+/// `NAME(rick)` -> operation, punctuation, operand, punctuation
+fn highlight_operation(segment: &str, offset: usize, tokens: &mut Vec<Token>) {
+    let Some(open) = segment.find('(') else {
+        tokens.push(token(offset..offset + segment.len(), TokenKind::Operation));
+        return;
+    };
+    tokens.push(token(offset..offset + open, TokenKind::Operation));
+    tokens.push(token(
+        offset + open..offset + open + 1,
+        TokenKind::Punctuation,
+    ));
+
+    let mut start = open + 1;
+    for (index, character) in segment[start..].char_indices() {
+        let position = open + 1 + index;
+        if character == ',' || character == ')' {
+            if position > start {
+                tokens.push(token(offset + start..offset + position, TokenKind::Operand));
+            }
+            tokens.push(token(
+                offset + position..offset + position + 1,
+                TokenKind::Punctuation,
+            ));
+            start = position + 1;
+        }
+    }
+    if start < segment.len() {
+        tokens.push(token(
+            offset + start..offset + segment.len(),
+            TokenKind::Operand,
+        ));
+    }
+}
+
+fn token(range: Range<usize>, kind: TokenKind) -> Token {
+    Token { range, kind }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<(&str, TokenKind)> {
+        highlight(input)
+            .into_iter()
+            .map(|token| (&input[token.range], token.kind))
+            .collect()
+    }
+
+    #[test]
+    fn highlights_operations_and_operands() {
+        assert_eq!(
+            kinds("CHARACTERS::SORT(ASC, name)"),
+            vec![
+                ("CHARACTERS", TokenKind::Root),
+                ("::", TokenKind::Separator),
+                ("SORT", TokenKind::Operation),
+                ("(", TokenKind::Punctuation),
+                ("ASC", TokenKind::Operand),
+                (",", TokenKind::Punctuation),
+                (" name", TokenKind::Operand),
+                (")", TokenKind::Punctuation),
+            ]
+        );
+        assert_eq!(kinds("@ricks(5)")[0], ("@ricks", TokenKind::Operation));
+    }
+
+    #[test]
+    fn marks_segments_that_fail_to_parse() {
+        assert_eq!(
+            kinds("EPISODES::NAEM(pilot)::LIMIT(2)")[2],
+            ("NAEM(pilot)", TokenKind::Invalid)
+        );
+    }
+
+    #[test]
+    fn highlights_bindings_and_pipelines() {
+        let input = "LET p = EPISODES::NAME(Pilot); CHARACTERS::IDS($p.characters) | EPISODES";
+        let tokens = highlight(input);
+        assert_eq!(
+            tokens.iter().map(|token| token.range.len()).sum::<usize>(),
+            input.len()
+        );
+        assert!(tokens.iter().all(|token| token.kind != TokenKind::Invalid));
+        let separated = kinds("CHARACTERS::NAME(a|b); EPISODES");
+        assert_eq!(separated[2], ("NAME", TokenKind::Operation));
+        assert_eq!(separated[4], ("a|b", TokenKind::Operand));
+
+        let kinds = kinds(input);
+        assert_eq!(kinds[0], ("LET", TokenKind::Operation));
+        assert_eq!(kinds[1], (" p ", TokenKind::Operand));
+        assert!(kinds.contains(&(";", TokenKind::Separator)));
+        assert!(kinds.contains(&("|", TokenKind::Separator)));
+        assert!(kinds.contains(&("$p.characters", TokenKind::Operand)));
+
+        let kinds = super::highlight("EXPLAIN EPISODES");
+        assert_eq!(kinds[0], token(0..7, TokenKind::Operation));
+        assert_eq!(kinds.last().unwrap().kind, TokenKind::Root);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::split_top_level;
+use crate::lib::error::RickError;
+
+const MACROS_FILE: &str = "macros.toml";
+/// Deeper expansions are taken for a macro using itself
+const MAX_DEPTH: usize = 16;
+
+/// Operations saved under a name, with `$param`s filled in by the caller.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Macro {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<String>,
+    pub query: String,
+}
+
+impl Macro {
+    /// How the macro is called, e.g `@top_by_name($n)`.
+    pub fn signature(&self, name: &str) -> String {
+        if self.params.is_empty() {
+            return format!("@{}", name);
+        }
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| format!("${}", param))
+            .collect();
+        format!("@{}({})", name, params.join(", "))
+    }
+}
+
+/// The saved macros by name, kept in a TOML file so they survive restarts.
+#[derive(Clone, Default, Debug)]
+pub struct Macros {
+    macros: BTreeMap<String, Macro>,
+    path: Option<PathBuf>,
+}
+
+impl Macros {
+    /// Macros that are never written to disk
+    pub fn in_memory() -> Self {
+        Macros::default()
+    }
+
+    /// Reads the macros in `dir`, starting empty when there are none yet.
+    pub fn load(dir: &Path) -> Result<Self, RickError> {
+        let path = dir.join(MACROS_FILE);
+        let macros = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|err| {
+                RickError::Parse(format!("in {}: {}", path.display(), err.message()))
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Macros {
+            macros,
+            path: Some(path),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Macro)> {
+        self.macros.iter()
+    }
+
+    /// Saves `query` under a signature like `top_by_name($n)`, replacing
+    /// any macro of that name.
+    pub fn save(&mut self, signature: &str, query: &str) -> Result<String, RickError> {
+        let (name, params) = parse_call(signature.trim().trim_start_matches('@'))?;
+        let params = params
+            .iter()
+            .map(|param| match param.strip_prefix('$') {
+                Some(param) if is_name(param) => Ok(param.to_string()),
+                _ => Err(RickError::Parse(format!(
+                    "expected a parameter like `$name`, found `{}`",
+                    param
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let saved = Macro {
+            params,
+            query: query.trim().to_string(),
+        };
+        // `$name.field` reads a `LET` binding, and would lose its field to
+        // an argument named the same
+        if let Some(param) = saved
+            .params
+            .iter()
+            .find(|param| references(&saved.query).any(|(name, field)| name == *param && field))
+        {
+            return Err(RickError::Parse(format!(
+                "`${}` is also used as `${}.field`, a `LET` binding, rename the parameter",
+                param, param
+            )));
+        }
+        for (index, segment) in split_top_level(&saved.query, "::").into_iter().enumerate() {
+            if index > 0 && segment.trim().is_empty() {
+                return Err(RickError::Parse(format!(
+                    "empty operation in `{}`",
+                    saved.query
+                )));
+            }
+        }
+        let signature = saved.signature(&name);
+        self.macros.insert(name, saved);
+        self.write()?;
+        Ok(signature)
+    }
+
+    /// Removes a macro, returning whether there was one.
+    pub fn remove(&mut self, name: &str) -> Result<bool, RickError> {
+        let removed = self.macros.remove(name.trim_start_matches('@')).is_some();
+        if removed {
+            self.write()?;
+        }
+        Ok(removed)
+    }
+
+    /// Replaces every `@name(args)` operation in `query` with the
+    /// operations saved under that name.
+    pub fn expand(&self, query: &str) -> Result<String, RickError> {
+        self.expand_within(query, 0)
+    }
+
+    fn expand_within(&self, query: &str, depth: usize) -> Result<String, RickError> {
+        let mut segments = vec![];
+        for segment in split_top_level(query, "::") {
+            let Some(call) = segment.trim().strip_prefix('@') else {
+                segments.push(segment.to_string());
+                continue;
+            };
+            let (name, args) = parse_call(call)?;
+            let saved = self
+                .macros
+                .get(&name)
+                .ok_or_else(|| RickError::Parse(format!("unknown macro `@{}`", name)))?;
+            if depth >= MAX_DEPTH {
+                return Err(RickError::Parse(format!(
+                    "macro `@{}` expands into itself",
+                    name
+                )));
+            }
+            if args.len() != saved.params.len() {
+                return Err(RickError::Parse(format!(
+                    "`{}` takes {} arguments, {} given",
+                    saved.signature(&name),
+                    saved.params.len(),
+                    args.len()
+                )));
+            }
+            let body = substitute(&saved.query, &saved.params, &args);
+            segments.push(self.expand_within(&body, depth + 1)?);
+        }
+        Ok(segments.join("::"))
+    }
+
+    fn write(&self) -> Result<(), RickError> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let contents =
+                toml::to_string(&self.macros).map_err(|err| RickError::Parse(err.to_string()))?;
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `name(a, b)` into the name and the trimmed arguments, the
+/// commas inside an argument's parentheses not splitting it.
+fn parse_call(call: &str) -> Result<(String, Vec<String>), RickError> {
+    let (name, args) = match call.split_once('(') {
+        Some((name, rest)) => {
+            let args = rest
+                .strip_suffix(')')
+                .ok_or_else(|| RickError::Parse(format!("missing `)` after `@{}`", call)))?;
+            let args = split_top_level(args, ",")
+                .into_iter()
+                .map(|arg| arg.trim().to_string())
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            (name, args)
+        }
+        None => (call, vec![]),
+    };
+    if !is_name(name) {
+        return Err(RickError::Parse(format!("`{}` is not a macro name", name)));
+    }
+    Ok((name.to_string(), args))
+}
+
+/// Replaces each whole `$param` in `query` with its argument, leaving
+/// other `$name`s, like `$pilot` for a parameter `p`, as they are.
+fn substitute(query: &str, params: &[String], args: &[String]) -> String {
+    let mut substituted = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(start) = rest.find('$') {
+        substituted.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find(|c: char| !is_name_char(c))
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        match params.iter().position(|param| param == name) {
+            Some(index) => substituted.push_str(&args[index]),
+            None => {
+                substituted.push('$');
+                substituted.push_str(name);
+            }
+        }
+        rest = &after[end..];
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+/// The `$name`s in `query`, and whether each is followed by a `.field`.
+fn references(query: &str) -> impl Iterator<Item = (&str, bool)> {
+    query.match_indices('$').map(move |(start, _)| {
+        let after = &query[start + 1..];
+        let end = after
+            .find(|c: char| !is_name_char(c))
+            .unwrap_or(after.len());
+        (&after[..end], after[end..].starts_with('.'))
+    })
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_name_char)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn macros() -> Macros {
+        let mut macros = Macros::in_memory();
+        macros
+            .save(
+                "top_by_name($n, $count)",
+                "NAME($n)::SORT(ASC, created)::LIMIT($count)",
+            )
+            .unwrap();
+        macros
+            .save("@ricks", "CHARACTERS::@top_by_name(rick, 5)")
+            .unwrap();
+        macros
+    }
+
+    #[test]
+    fn expands_macros() {
+        let macros = macros();
+        assert_eq!(
+            macros.expand("CHARACTERS::@top_by_name(morty, 3)").unwrap(),
+            "CHARACTERS::NAME(morty)::SORT(ASC, created)::LIMIT(3)"
+        );
+        assert_eq!(
+            macros.expand("@ricks::PAGE(2)").unwrap(),
+            "CHARACTERS::NAME(rick)::SORT(ASC, created)::LIMIT(5)::PAGE(2)"
+        );
+        assert_eq!(macros.expand("EPISODES").unwrap(), "EPISODES");
+    }
+
+    #[test]
+    fn substitutes_whole_parameters_only() {
+        let mut macros = Macros::in_memory();
+        macros
+            .save("with($p)", "CHARACTERS::IDS($pilot.characters)::NAME($p)")
+            .unwrap();
+        assert_eq!(
+            macros.expand("@with(rick)").unwrap(),
+            "CHARACTERS::IDS($pilot.characters)::NAME(rick)"
+        );
+        assert_eq!(
+            macros
+                .save("with($pilot)", "CHARACTERS::IDS($pilot.characters)")
+                .unwrap_err()
+                .to_string(),
+            "`$pilot` is also used as `$pilot.field`, a `LET` binding, rename the parameter"
+        );
+    }
+
+    #[test]
+    fn keeps_parenthesised_arguments_whole() {
+        let mut macros = Macros::in_memory();
+        macros
+            .save(
+                "filtered($filter, $count)",
+                "CHARACTERS::$filter::LIMIT($count)",
+            )
+            .unwrap();
+        assert_eq!(
+            macros
+                .expand("@filtered(CONTAINS(status, ive), 3)")
+                .unwrap(),
+            "CHARACTERS::CONTAINS(status, ive)::LIMIT(3)"
+        );
+    }
+
+    #[test]
+    fn reports_bad_calls() {
+        let mut macros = macros();
+        assert_eq!(
+            macros
+                .expand("CHARACTERS::@top_by_name(rick)")
+                .unwrap_err()
+                .to_string(),
+            "`@top_by_name($n, $count)` takes 2 arguments, 1 given"
+        );
+        assert!(macros.expand("@nope").is_err());
+        assert!(macros.save("bad name", "EPISODES").is_err());
+        assert!(macros.save("f(n)", "EPISODES").is_err());
+
+        macros.save("loop", "@loop").unwrap();
+        assert!(macros
+            .expand("@loop")
+            .unwrap_err()
+            .to_string()
+            .contains("expands into itself"));
+        assert!(macros.remove("@loop").unwrap());
+        assert!(!macros.remove("loop").unwrap());
+    }
+
+    #[test]
+    fn saves_to_disk() {
+        let dir = std::env::temp_dir().join(format!("rick_cli_macros_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut macros = Macros::load(&dir).unwrap();
+        macros.save("pilot", "EPISODES::NAME(Pilot)").unwrap();
+
+        let loaded = Macros::load(&dir).unwrap();
+        assert_eq!(loaded.expand("@pilot").unwrap(), "EPISODES::NAME(Pilot)");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
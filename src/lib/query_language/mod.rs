@@ -1,11 +1,45 @@
+pub mod completion;
+pub mod fields;
+pub mod highlight;
+pub mod macros;
 pub mod operand;
 pub mod operation;
 pub mod operation_list;
+pub mod validate;
+
+/// Splits `input` at each `separator` outside parentheses and double
+/// quotes, so the operands of `CONTAINS(name, x;y)` or `NAME(a::b)` and a
+/// path like `"a;b.csv"` stay whole.
+pub fn split_top_level<'a>(input: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in input.char_indices() {
+        if index < start {
+            // inside a separator just split at
+            continue;
+        }
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && input[index..].starts_with(separator) => {
+                parts.push(&input[start..index]);
+                start = index + separator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
 
 // tests
 #[cfg(test)]
 mod parsing_tests {
-    use super::{*, operation_list::*,operand::*,operation::*};
+    use super::{operation_list::*,operand::*,operation::*};
 
     #[test]
     fn test_roo() {
@@ -88,4 +122,57 @@ mod parsing_tests {
 
         assert_eq!(parsed_operation_list, expected_operation_list);
     }
+
+    #[test]
+    fn replacing_the_page() {
+        let operation_list = OperationList::parse_str("CHARACTERS::NAME(rick)::LIMIT(5)").unwrap();
+        assert_eq!(operation_list.page(), 1);
+
+        let next_page = operation_list.with_page(2);
+        assert_eq!(next_page.page(), 2);
+        assert_eq!(next_page.to_string(), "CHARACTERS::PAGE(2)::NAME(rick)::LIMIT(5)");
+        assert_eq!(next_page.with_page(3).to_string(), "CHARACTERS::PAGE(3)::NAME(rick)::LIMIT(5)");
+    }
+
+    #[test]
+    fn adding_a_default_limit() {
+        let operation_list = OperationList::parse_str("EPISODES::NAME(pilot)").unwrap();
+        assert_eq!(operation_list.with_default_limit(5).to_string(), "EPISODES::NAME(pilot)::LIMIT(5)");
+        let limited = OperationList::parse_str("EPISODES::LIMIT(2)").unwrap();
+        assert_eq!(limited.with_default_limit(5), limited);
+    }
+
+    #[test]
+    fn splitting_outside_parentheses() {
+        assert_eq!(
+            super::split_top_level("a(b, c), d, (e, (f)), g", ","),
+            vec!["a(b, c)", " d", " (e, (f))", " g"]
+        );
+        assert_eq!(
+            super::split_top_level("EXPORT csv \"a;b.csv\"; EPISODES", ";"),
+            vec!["EXPORT csv \"a;b.csv\"", " EPISODES"]
+        );
+        assert_eq!(
+            super::split_top_level("CHARACTERS::NAME(a::b)::LIMIT(2)", "::"),
+            vec!["CHARACTERS", "NAME(a::b)", "LIMIT(2)"]
+        );
+        assert_eq!(super::split_top_level("", ";"), vec![""]);
+    }
+
+    #[test]
+    fn unexpanded_macros_are_a_parse_error() {
+        assert_eq!(
+            OperationList::parse_str("@pilot").unwrap_err().to_string(),
+            "unknown macro `@pilot`"
+        );
+    }
+
+    #[test]
+    fn unknown_operation_is_a_parse_error() {
+        let parsed_operation_list = OperationList::parse_str("CHARACTERS::FOO(1)");
+        assert!(matches!(
+            parsed_operation_list,
+            Err(crate::lib::error::RickError::Parse(_))
+        ));
+    }
 }
@@ -3,8 +3,8 @@
 pub enum OperandEnum {
     Number(f32),
     String(String),
-   
-    // NumberList(Vec<f32>),
+    /// e.g the ids of `IDS(1, 2, 3)`
+    NumberList(Vec<f32>),
     // StringList(Vec<String>),
 }
 #[derive(Debug, PartialEq, Clone)]
@@ -15,6 +15,7 @@ impl From<Operand> for f32 {
         match operand.0 {
             OperandEnum::Number(n) => n,
             OperandEnum::String(s) => s.parse::<f32>().unwrap_or_default(),
+            OperandEnum::NumberList(_) => 0.0,
         }
     }
 }
@@ -24,6 +25,7 @@ impl From<&Operand> for f32 {
         match &operand.0 {
             OperandEnum::Number(n) => *n,
             OperandEnum::String(s) => s.parse::<f32>().unwrap_or(0.0),
+            OperandEnum::NumberList(_) => 0.0,
         }
     }
 }
@@ -33,6 +35,7 @@ impl From<Operand> for u32 {
         match operand.0 {
             OperandEnum::Number(n) => n as u32,
             OperandEnum::String(s) => s.parse::<u32>().unwrap_or(0),
+            OperandEnum::NumberList(_) => 0,
         }
     }
 }
@@ -42,6 +45,7 @@ impl From<&Operand> for u32 {
         match &operand.0 {
             OperandEnum::Number(n) => *n as u32,
             OperandEnum::String(s) => s.parse::<u32>().unwrap_or(0),
+            OperandEnum::NumberList(_) => 0,
         }
     }
 }
@@ -52,6 +56,7 @@ impl From<&Operand> for String {
         match &operand.0 {
             OperandEnum::Number(n) => n.to_string(),
             OperandEnum::String(s) => s.clone(),
+            OperandEnum::NumberList(numbers) => join(numbers),
         }
     }
 }
@@ -61,6 +66,15 @@ impl From<Operand> for String {
         match operand.0 {
             OperandEnum::Number(n) => n.to_string(),
             OperandEnum::String(s) => s,
+            OperandEnum::NumberList(numbers) => join(&numbers),
         }
     }
 }
+
+fn join(numbers: &[f32]) -> String {
+    numbers
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,7 +1,9 @@
 use super::operand::{Operand, OperandEnum};
+use crate::lib::error::RickError;
 use regex_lexer_lalrpop::LexerBuilder;
-use std::{error::Error, ops::Deref};
+use std::ops::Deref;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum Root {
     CHARACTERS,
@@ -10,7 +12,7 @@ pub enum Root {
     LOCATIONS,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum OperationEnum {
     Root(Root), //Every operation starts with a root
     Name(Operand),
@@ -22,10 +24,11 @@ pub enum OperationEnum {
     Limit(Operand),
     Index(Operand),
     Sort(Operand, Operand),
-  
+    /// Selects results by id instead of the server side filters
+    Ids(Operand),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Operation(pub OperationEnum);
 
 impl From<Operation> for String {
@@ -63,8 +66,7 @@ impl From<Operation> for String {
             // OperationEnum::Pick(operand) => format!("PICK({})", String::from(operand)),
             OperationEnum::Dimension(operand) => format!("DIMENSION({})", String::from(operand)),
             OperationEnum::Limit(operand) => format!("LIMIT({})", String::from(operand)),
-          
-
+            OperationEnum::Ids(operand) => format!("IDS({})", String::from(operand)),
 
         }
     }
@@ -104,6 +106,7 @@ impl From<&Operation> for String {
             }
             OperationEnum::Dimension(operand) => format!("DIMENSION({})", String::from(operand)),
             OperationEnum::Limit(operand) => format!("LIMIT({})", String::from(operand)),
+            OperationEnum::Ids(operand) => format!("IDS({})", String::from(operand)),
             // OperationEnum::Pick(operand) => format!("PICK({})", String::from(operand)),
         }
     }
@@ -118,7 +121,7 @@ impl Operation {
             .to_string()
     }
 
-    pub fn parse_str(operation_string: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn parse_str(operation_string: &str) -> Result<Self, RickError> {
         let lexer = LexerBuilder::new()
             .token(r"NAME\((.*?)\)", |_, value, _| {
                 Some(Operation(OperationEnum::Name(Operand(
@@ -160,9 +163,14 @@ impl Operation {
                 let first = split.next().unwrap();
                 let second = split.next().unwrap();
 
+                let second = match second.parse::<f32>() {
+                    Ok(length) => OperandEnum::Number(length),
+                    Err(_) => OperandEnum::String(second.to_string()),
+                };
+
                 Some(Operation(OperationEnum::Length(
                     Operand(OperandEnum::String(first.to_string())),
-                    Operand(OperandEnum::String(second.to_string())),
+                    Operand(second),
                 )))
             })
             .token(r"DIMENSION\((.*?)\)", |_, value, _| {
@@ -196,12 +204,25 @@ impl Operation {
                     ),
                 ))))
             })
-            .build()?;
+            .token(r"IDS\((.*?)\)", |_, value, _| {
+                // at least one id, every one a whole number
+                let ids = Operation::remove_symbols(value, "IDS")
+                    .split(',')
+                    .map(|id| id.trim().parse::<u32>().map(|id| id as f32))
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()?;
+                Some(Operation(OperationEnum::Ids(Operand(OperandEnum::NumberList(ids)))))
+            })
+            .build()
+            .map_err(|err| RickError::Parse(err.to_string()))?;
 
         let mut tokens = lexer.tokens(operation_string);
         match tokens.next() {
             Some(Ok(operation)) => Ok(operation),
-            _ => Err("Operation not found".into()),
+            _ => Err(RickError::Parse(format!(
+                "Operation not found: `{}`",
+                operation_string
+            ))),
         }
     }
 }
@@ -312,6 +333,18 @@ mod tests {
         assert_eq!(parsed_operation, expected_operation);
     }
 
+    #[test]
+    fn test_parse_str_ids() {
+        let parsed_operation = Operation::parse_str("IDS(1, 2,3)").unwrap();
+        let expected_operation =
+            OperationEnum::Ids(Operand(OperandEnum::NumberList(vec![1.0, 2.0, 3.0])));
+
+        assert_eq!(parsed_operation.0, expected_operation);
+        assert_eq!(String::from(parsed_operation), "IDS(1, 2, 3)");
+        assert!(Operation::parse_str("IDS()").is_err());
+        assert!(Operation::parse_str("IDS(1, x)").is_err());
+    }
+
     #[test]
     fn test_parse_str_limit() {
         let parsed_operation = Operation::parse_str("LIMIT(10)").unwrap().0;
@@ -1,10 +1,14 @@
-use crate::lib::rm_api::response::RMResponse;
+use crate::lib::{error::RickError, rm_api::response::RMResponse};
 use async_trait::async_trait;
-use std::{ops::Deref, error::Error};
+use std::{fmt, ops::Deref};
 
-use super::operation::Operation;
+use super::{
+    operand::{Operand, OperandEnum},
+    operation::{Operation, OperationEnum},
+    split_top_level,
+};
 
-#[derive(Debug, PartialEq,)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct OperationList(pub Vec<Operation>);
 
 #[async_trait]
@@ -12,26 +16,72 @@ pub trait OperationListEvaluator {
     async fn evaluate_op(
         &self,
         operation_list: &OperationList,
-    ) -> Result<RMResponse, Box<dyn std::error::Error>>;
+    ) -> Result<RMResponse, RickError>;
 }
 
-impl OperationList {
-    pub fn to_string(&self) -> String {
-        self.0
+impl fmt::Display for OperationList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operations = self
+            .0
             .iter()
             .map(|op| op.into())
             .collect::<Vec<String>>()
-            .join("::")
+            .join("::");
+        write!(f, "{}", operations)
     }
+}
 
-    pub fn parse_str(operation_list_string: &str) -> Result<Self, Box<dyn Error>> {
+impl OperationList {
+    /// Parses operations as written. Macros are expanded when a plan is
+    /// read by `Plan::parse_with`, so an `@name` left here is unknown.
+    pub fn parse_str(operation_list_string: &str) -> Result<Self, RickError> {
         let mut operation_list = OperationList(vec![]);
-        for operation_string in operation_list_string.split("::") {
+        for operation_string in split_top_level(operation_list_string, "::") {
+            if let Some(call) = operation_string.trim().strip_prefix('@') {
+                let name = call.split('(').next().unwrap_or_default();
+                return Err(RickError::Parse(format!("unknown macro `@{}`", name)));
+            }
             let parsed_operation = Operation::parse_str(operation_string)?;
             operation_list.0.push(parsed_operation);
         }
         Ok(operation_list)
     }
+
+    /// The page the list asks the server for, 1 when there is no PAGE operation.
+    pub fn page(&self) -> u32 {
+        self.iter()
+            .find_map(|operation| match &operation.0 {
+                OperationEnum::Page(page) => Some(u32::from(page).max(1)),
+                _ => None,
+            })
+            .unwrap_or(1)
+    }
+
+    /// A copy of the list asking for `page` instead, right after the root.
+    pub fn with_page(&self, page: u32) -> OperationList {
+        let mut operations = self
+            .iter()
+            .filter(|operation| !matches!(operation.0, OperationEnum::Page(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let position = operations.len().min(1);
+        operations.insert(
+            position,
+            Operation(OperationEnum::Page(Operand(OperandEnum::Number(page as f32)))),
+        );
+        OperationList(operations)
+    }
+
+    /// A copy of the list ending in `LIMIT(limit)`, unless it has a LIMIT already.
+    pub fn with_default_limit(&self, limit: u32) -> OperationList {
+        let mut operation_list = self.clone();
+        if !self.iter().any(|operation| matches!(operation.0, OperationEnum::Limit(_))) {
+            operation_list.0.push(Operation(OperationEnum::Limit(Operand(OperandEnum::Number(
+                limit as f32,
+            )))));
+        }
+        operation_list
+    }
 }
 
 impl From<OperationList> for String {
@@ -0,0 +1,324 @@
+use std::fmt;
+
+use crate::lib::error::RickError;
+
+use super::{
+    fields::Fields,
+    operation::{Operation, OperationEnum, Root},
+    operation_list::OperationList,
+    split_top_level,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The query cannot run as written
+    Error,
+    /// The query runs, but part of it has no effect
+    Warning,
+}
+
+/// A problem with one operation of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Where the operation is in the chain, the root being 0
+    pub position: usize,
+    /// The operation as written, empty for problems with the whole query
+    pub operation: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, position: usize, operation: &Operation, message: String) -> Self {
+        Diagnostic {
+            severity,
+            position,
+            operation: String::from(operation),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Parses a query one operation at a time and checks it against what each
+/// root accepts: the root comes first and only first, operations apply to
+/// the root, and fields exist on it. An operation that does not parse is
+/// reported alongside the problems of the others, every problem in the
+/// order of the operations. The list holds the operations that parsed, and
+/// only runs as written when there is no error.
+pub fn parse_and_validate(input: &str) -> (OperationList, Vec<Diagnostic>) {
+    let mut parsed = vec![];
+    let mut diagnostics = vec![];
+    for (position, segment) in split_top_level(input, "::").into_iter().enumerate() {
+        let text = segment.trim();
+        let parsed_operation = match text.strip_prefix('@') {
+            Some(call) => Err(RickError::Parse(format!(
+                "unknown macro `@{}`",
+                call.split('(').next().unwrap_or_default()
+            ))),
+            None => Operation::parse_str(segment),
+        };
+        match parsed_operation {
+            Ok(operation) => parsed.push(Some(operation)),
+            Err(error) => {
+                parsed.push(None);
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    position,
+                    operation: text.to_string(),
+                    message: error.to_string(),
+                });
+            }
+        }
+    }
+    diagnostics.extend(check(
+        &parsed.iter().map(Option::as_ref).collect::<Vec<_>>(),
+    ));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    (
+        OperationList(parsed.into_iter().flatten().collect()),
+        diagnostics,
+    )
+}
+
+/// The checks of `parse_and_validate`, a `None` being an operation that did
+/// not parse and is skipped. Without a parsed first operation the root is
+/// unknown, so nothing depending on it is checked.
+fn check(operations: &[Option<&Operation>]) -> Vec<Diagnostic> {
+    let Some(first) = operations.first() else {
+        return vec![Diagnostic {
+            severity: Severity::Error,
+            position: 0,
+            operation: String::new(),
+            message: "a query needs a root, e.g CHARACTERS".to_string(),
+        }];
+    };
+    let root = match first.map(|operation| &operation.0) {
+        Some(OperationEnum::Root(root)) => Some(root),
+        _ => None,
+    };
+    let selects_ids = operations
+        .iter()
+        .flatten()
+        .any(|operation| matches!(operation.0, OperationEnum::Ids(_)));
+
+    let mut diagnostics = vec![];
+    for (position, operation) in operations.iter().enumerate() {
+        let Some(operation) = *operation else {
+            continue;
+        };
+        let text = String::from(operation);
+        let mut report = |severity, message: String| {
+            diagnostics.push(Diagnostic::new(severity, position, operation, message));
+        };
+        match (&operation.0, root) {
+            (OperationEnum::Root(_), _) if position > 0 => report(
+                Severity::Error,
+                format!("`{}` can only start a query", text),
+            ),
+            (_, None) if position == 0 => report(
+                Severity::Error,
+                format!(
+                    "`{}` cannot start a query, start with CHARACTERS, EPISODES or LOCATIONS",
+                    text
+                ),
+            ),
+            (OperationEnum::Dimension(_), Some(root)) if *root != Root::LOCATIONS => report(
+                Severity::Error,
+                format!("`{}` only applies to LOCATIONS", text),
+            ),
+            (OperationEnum::Page(page), _) if u32::from(page) == 0 => {
+                report(Severity::Error, format!("`{}` counts pages from 1", text))
+            }
+            (OperationEnum::Contains(field, _), Some(root)) => {
+                let fields = Fields::for_root(root);
+                if let Err(error) = fields.check_searchable(&String::from(field)) {
+                    let expected = fields.searchable.join(", ");
+                    report(
+                        Severity::Error,
+                        format!("`{}`: {}, expected one of {}", text, error, expected),
+                    );
+                }
+            }
+            (OperationEnum::Sort(_, field), Some(root)) => {
+                let fields = Fields::for_root(root);
+                if let Err(error) = fields.check_sortable(&String::from(field)) {
+                    let expected = fields.sortable.join(", ");
+                    report(
+                        Severity::Error,
+                        format!("`{}`: {}, expected one of {}", text, error, expected),
+                    );
+                }
+            }
+            (OperationEnum::Length(_, _) | OperationEnum::Index(_), _) => report(
+                Severity::Warning,
+                format!("`{}` is not implemented and is ignored", text),
+            ),
+            _ => {}
+        }
+
+        let parameter = matches!(
+            operation.0,
+            OperationEnum::Name(_)
+                | OperationEnum::Page(_)
+                | OperationEnum::Dimension(_)
+                | OperationEnum::Ids(_)
+        );
+        let replaced = operations[position + 1..]
+            .iter()
+            .flatten()
+            .any(|later| same_parameter(operation, later));
+        if parameter && replaced {
+            report(
+                Severity::Warning,
+                format!("`{}` is replaced by a later one", text),
+            );
+        } else if parameter && selects_ids && !matches!(operation.0, OperationEnum::Ids(_)) {
+            report(
+                Severity::Warning,
+                format!("`{}` is ignored when selecting by IDS", text),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// The problems that keep a query from running.
+pub fn errors(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .collect()
+}
+
+/// Whether two operations set the same URL parameter, so the later one wins.
+fn same_parameter(operation: &Operation, other: &Operation) -> bool {
+    matches!(
+        (&operation.0, &other.0),
+        (OperationEnum::Name(_), OperationEnum::Name(_))
+            | (OperationEnum::Page(_), OperationEnum::Page(_))
+            | (OperationEnum::Dimension(_), OperationEnum::Dimension(_))
+            | (OperationEnum::Ids(_), OperationEnum::Ids(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(query: &str) -> Vec<(Severity, usize, String)> {
+        parse_and_validate(query)
+            .1
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.position, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_queries() {
+        assert!(messages(
+            "CHARACTERS::NAME(rick)::CONTAINS(status, ive)::SORT(DSC, name)::LIMIT(3)"
+        )
+        .is_empty());
+        assert!(messages("LOCATIONS::DIMENSION(C-137)::PAGE(2)").is_empty());
+        assert!(messages("EPISODES::IDS(1, 2)::SORT(ASC, characters)").is_empty());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        assert_eq!(
+            messages("EPISODES::DIMENSION(C-137)::CONTAINS(status, ive)::CHARACTERS::SORT(ASC, height)::PAGE(0)"),
+            vec![
+                (Severity::Error, 1, "`DIMENSION(C-137)` only applies to LOCATIONS".to_string()),
+                (
+                    Severity::Error,
+                    2,
+                    "`CONTAINS(status, ive)`: episodes have no field named `status`, expected one of id, name, air_date, episode"
+                        .to_string()
+                ),
+                (Severity::Error, 3, "`CHARACTERS` can only start a query".to_string()),
+                (
+                    Severity::Error,
+                    4,
+                    "`SORT(ASC, height)`: episodes have no field named `height`, expected one of id, name, air_date, episode, characters, created"
+                        .to_string()
+                ),
+                (Severity::Error, 5, "`PAGE(0)` counts pages from 1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_operations_without_effect() {
+        assert_eq!(
+            messages("CHARACTERS::NAME(morty)::IDS(1)::NAME(rick)::LENGTH(name, 3)"),
+            vec![
+                (
+                    Severity::Warning,
+                    1,
+                    "`NAME(morty)` is replaced by a later one".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    3,
+                    "`NAME(rick)` is ignored when selecting by IDS".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    4,
+                    "`LENGTH(name, 3)` is not implemented and is ignored".to_string()
+                ),
+            ]
+        );
+        let (_, diagnostics) = parse_and_validate("NAME(rick)::LIMIT(2)");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].operation, "NAME(rick)");
+        assert!(errors(check(&[])).len() == 1);
+    }
+
+    #[test]
+    fn reports_parse_errors_with_the_other_problems() {
+        let (operation_list, diagnostics) =
+            parse_and_validate("EPISODES::NAEM(rick)::DIMENSION(C-137)::@nope::LIMIT(2)");
+        assert_eq!(
+            operation_list.to_string(),
+            "EPISODES::DIMENSION(C-137)::LIMIT(2)"
+        );
+        assert_eq!(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (
+                    diagnostic.severity,
+                    diagnostic.position,
+                    diagnostic.operation
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (Severity::Error, 1, "NAEM(rick)".to_string()),
+                (Severity::Error, 2, "DIMENSION(C-137)".to_string()),
+                (Severity::Error, 3, "@nope".to_string()),
+            ]
+        );
+        assert_eq!(
+            messages("CHARACTERS::CONTAINS(name, a::b)::PAGE(0)"),
+            vec![(
+                Severity::Error,
+                2,
+                "`PAGE(0)` counts pages from 1".to_string()
+            )]
+        );
+        assert_eq!(
+            messages("NAEM(rick)::LIMIT(2)"),
+            vec![(
+                Severity::Error,
+                0,
+                "Operation not found: `NAEM(rick)`".to_string()
+            )]
+        );
+    }
+}
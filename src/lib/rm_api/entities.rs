@@ -1,12 +1,12 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Page<T> {
     pub info: Info,
     pub results: Vec<T>,
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Info {
     pub count: u32,
     pub pages: u32,
@@ -14,7 +14,7 @@ pub struct Info {
     pub prev: Option<String>,
 }
 
-#[derive(Default,Clone, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Location {
     pub id: u32,
     pub name: String,
@@ -26,7 +26,7 @@ pub struct Location {
     pub created: String,
 }
 
-#[derive(Default,Clone, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Episode {
     pub id: u32,
     pub name: String,
@@ -37,7 +37,7 @@ pub struct Episode {
     pub created: String,
 }
 
-#[derive(Default,Clone, Debug, PartialEq, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Character {
     pub id: u32,
     pub name: String,
@@ -55,3 +55,87 @@ pub struct Character {
 pub type CharacterPage = Page<Character>;
 pub type EpisodePage = Page<Episode>;
 pub type LocationPage = Page<Location>;
+
+/// Read access to an entity's fields by the names the API uses for them.
+pub trait Entity {
+    const FIELD_NAMES: &'static [&'static str];
+
+    /// The field formatted as text, lists of urls become the ids they point to.
+    fn field(&self, name: &str) -> Option<String>;
+
+    /// Every field with its name, in `FIELD_NAMES` order
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        Self::FIELD_NAMES
+            .iter()
+            .map(|name| (*name, self.field(name).unwrap_or_default()))
+            .collect()
+    }
+}
+
+/// `https://rickandmortyapi.com/api/character/1` -> `1`
+fn url_ids(urls: &[String]) -> String {
+    urls.iter()
+        .filter_map(|url| url.rsplit('/').next())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Entity for Character {
+    const FIELD_NAMES: &'static [&'static str] = &[
+        "id", "name", "status", "species", "type", "gender", "image", "episode", "url", "created",
+    ];
+
+    fn field(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "id" => self.id.to_string(),
+            "name" => self.name.clone(),
+            "status" => self.status.clone(),
+            "species" => self.species.clone(),
+            "type" => self._type.clone(),
+            "gender" => self.gender.clone(),
+            "image" => self.image.clone(),
+            "episode" => url_ids(&self.episode),
+            "url" => self.url.clone(),
+            "created" => self.created.clone(),
+            _ => return None,
+        })
+    }
+}
+
+impl Entity for Location {
+    const FIELD_NAMES: &'static [&'static str] = &[
+        "id", "name", "type", "dimension", "residents", "url", "created",
+    ];
+
+    fn field(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "id" => self.id.to_string(),
+            "name" => self.name.clone(),
+            "type" => self._type.clone(),
+            "dimension" => self.dimension.clone(),
+            "residents" => url_ids(&self.residents),
+            "url" => self.url.clone(),
+            "created" => self.created.clone(),
+            _ => return None,
+        })
+    }
+}
+
+impl Entity for Episode {
+    const FIELD_NAMES: &'static [&'static str] = &[
+        "id", "name", "air_date", "episode", "characters", "url", "created",
+    ];
+
+    fn field(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "id" => self.id.to_string(),
+            "name" => self.name.clone(),
+            "air_date" => self.air_date.clone(),
+            "episode" => self.episode.clone(),
+            "characters" => url_ids(&self.characters),
+            "url" => self.url.clone(),
+            "created" => self.created.clone(),
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use super::{
+    super::error::RickError,
+    entities::*,
+    request::builder::{CharactersRequest, EpisodesRequest, LocationsRequest, RequestURLBuilder},
+    Rickuest, NOTHING_HERE,
+};
+
+/// Number of results the upstream API returns per page.
+pub const PAGE_SIZE: usize = 20;
+
+const MIRROR_FILE: &str = "mirror.json";
+
+/// A complete copy of every character, location and episode.
+///
+/// Besides persisting the mirror, the store can answer the same URLs the
+/// upstream server does, emulating its filters and pagination locally.
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LocalStore {
+    pub characters: Vec<Character>,
+    pub locations: Vec<Location>,
+    pub episodes: Vec<Episode>,
+}
+
+impl LocalStore {
+    pub fn load(dir: &Path) -> Result<Self, RickError> {
+        let path = dir.join(MIRROR_FILE);
+        let contents = fs::read_to_string(&path).map_err(|err| {
+            RickError::Io(format!(
+                "could not read local mirror at {} ({}), run `rick_cli sync` first",
+                path.display(),
+                err
+            ))
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), RickError> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(MIRROR_FILE), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Downloads every page of every endpoint, following `Info.next`.
+    pub async fn sync<T: Rickuest + Clone + Send>(
+        request: T,
+        domain: &str,
+    ) -> Result<Self, RickError> {
+        let mut store = LocalStore::default();
+
+        let mut next = Some(CharactersRequest::new(domain).build_url());
+        while let Some(url) = next {
+            let page = request.clone().get_characters(url).await?;
+            store.characters.extend(page.results);
+            next = page.info.next;
+        }
+
+        let mut next = Some(LocationsRequest::new(domain).build_url());
+        while let Some(url) = next {
+            let page = request.clone().get_locations(url).await?;
+            store.locations.extend(page.results);
+            next = page.info.next;
+        }
+
+        let mut next = Some(EpisodesRequest::new(domain, vec![]).build_url());
+        while let Some(url) = next {
+            let page = request.clone().get_episodes(url).await?;
+            store.episodes.extend(page.results);
+            next = page.info.next;
+        }
+
+        Ok(store)
+    }
+
+    pub fn characters_page(&self, url: &str) -> Result<CharacterPage, RickError> {
+        serve(
+            url,
+            &self.characters,
+            |character| character.id,
+            |character, key, value| match key {
+                "name" => contains_ignore_case(&character.name, value),
+                "species" => contains_ignore_case(&character.species, value),
+                "type" => contains_ignore_case(&character._type, value),
+                "status" => character.status.eq_ignore_ascii_case(value),
+                "gender" => character.gender.eq_ignore_ascii_case(value),
+                _ => true,
+            },
+        )
+    }
+
+    pub fn locations_page(&self, url: &str) -> Result<LocationPage, RickError> {
+        serve(
+            url,
+            &self.locations,
+            |location| location.id,
+            |location, key, value| match key {
+                "name" => contains_ignore_case(&location.name, value),
+                "type" => contains_ignore_case(&location._type, value),
+                "dimension" => contains_ignore_case(&location.dimension, value),
+                _ => true,
+            },
+        )
+    }
+
+    pub fn episodes_page(&self, url: &str) -> Result<EpisodePage, RickError> {
+        serve(
+            url,
+            &self.episodes,
+            |episode| episode.id,
+            |episode, key, value| match key {
+                "name" => contains_ignore_case(&episode.name, value),
+                "episode" => contains_ignore_case(&episode.episode, value),
+                _ => true,
+            },
+        )
+    }
+}
+
+/// The error the upstream server answers with when nothing matches.
+fn nothing_here() -> RickError {
+    RickError::Api {
+        status: 404,
+        message: NOTHING_HERE.to_string(),
+    }
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Answers an API url from `items` the way the upstream server would:
+/// `/api/<resource>/1,2,3` selects by id, otherwise query parameters filter
+/// the collection and `page` selects a window of `PAGE_SIZE` results.
+fn serve<T: Clone>(
+    url: &str,
+    items: &[T],
+    id: impl Fn(&T) -> u32,
+    matches: impl Fn(&T, &str, &str) -> bool,
+) -> Result<Page<T>, RickError> {
+    let parsed_url = reqwest::Url::parse(url)
+        .map_err(|err| RickError::Parse(format!("invalid url {} ({})", url, err)))?;
+    let ids = parsed_url
+        .path_segments()
+        .and_then(|mut segments| segments.nth(2))
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            segment
+                .split(',')
+                .map(|id| id.trim().parse::<u32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| RickError::Parse(format!("invalid id in {} ({})", url, err)))
+        })
+        .transpose()?;
+
+    if let Some(ids) = ids {
+        let results = ids
+            .iter()
+            .filter_map(|wanted| items.iter().find(|item| id(item) == *wanted))
+            .cloned()
+            .collect::<Vec<_>>();
+        if results.is_empty() {
+            return Err(nothing_here());
+        }
+        return Ok(Page {
+            info: Info {
+                count: results.len() as u32,
+                pages: 1,
+                next: None,
+                prev: None,
+            },
+            results,
+        });
+    }
+
+    let params = parsed_url
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    let page_number = params
+        .iter()
+        .find(|(key, _)| key == "page")
+        .map(|(_, value)| value.parse::<usize>().unwrap_or(1))
+        .unwrap_or(1)
+        .max(1);
+    let filtered = items
+        .iter()
+        .filter(|item| {
+            params
+                .iter()
+                .filter(|(key, _)| key != "page")
+                .all(|(key, value)| matches(item, key, value))
+        })
+        .collect::<Vec<_>>();
+
+    let count = filtered.len();
+    let pages = count.div_ceil(PAGE_SIZE);
+    if page_number > pages {
+        return Err(nothing_here());
+    }
+
+    let results = filtered
+        .into_iter()
+        .skip((page_number - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .cloned()
+        .collect();
+    let page_url = |number: usize| {
+        let mut page_url = parsed_url.clone();
+        page_url
+            .query_pairs_mut()
+            .clear()
+            .append_pair("page", &number.to_string())
+            .extend_pairs(params.iter().filter(|(key, _)| key != "page"));
+        page_url.to_string()
+    };
+
+    Ok(Page {
+        info: Info {
+            count: count as u32,
+            pages: pages as u32,
+            next: (page_number < pages).then(|| page_url(page_number + 1)),
+            prev: (page_number > 1).then(|| page_url(page_number - 1)),
+        },
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> LocalStore {
+        LocalStore {
+            characters: (1..=45)
+                .map(|id| Character {
+                    id,
+                    name: if id % 2 == 0 {
+                        format!("Morty {}", id)
+                    } else {
+                        format!("Rick {}", id)
+                    },
+                    status: "Alive".into(),
+                    ..Default::default()
+                })
+                .collect(),
+            locations: vec![
+                Location {
+                    id: 1,
+                    name: "Earth (C-137)".into(),
+                    dimension: "Dimension C-137".into(),
+                    ..Default::default()
+                },
+                Location {
+                    id: 2,
+                    name: "Abadango".into(),
+                    dimension: "unknown".into(),
+                    ..Default::default()
+                },
+            ],
+            episodes: vec![Episode {
+                id: 1,
+                name: "Pilot".into(),
+                episode: "S01E01".into(),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn paginates_like_upstream() {
+        let store = store();
+        let url = CharactersRequest::new("https://rickandmortyapi.com").build_url();
+        let page = store.characters_page(&url).unwrap();
+        assert_eq!(page.info.count, 45);
+        assert_eq!(page.info.pages, 3);
+        assert_eq!(page.results.len(), PAGE_SIZE);
+        assert_eq!(page.info.prev, None);
+
+        let next = page.info.next.unwrap();
+        let page = store.characters_page(&next).unwrap();
+        assert_eq!(page.results[0].id, 21);
+        assert!(page.info.prev.is_some());
+
+        let url = CharactersRequest::new("https://rickandmortyapi.com")
+            .page(4)
+            .build_url();
+        assert!(store.characters_page(&url).is_err());
+    }
+
+    #[test]
+    fn emulates_server_side_filters() {
+        let store = store();
+        let url = CharactersRequest::new("https://rickandmortyapi.com")
+            .name("rick")
+            .build_url();
+        let page = store.characters_page(&url).unwrap();
+        assert_eq!(page.info.count, 23);
+        assert!(page.info.next.unwrap().contains("name=rick"));
+
+        let url = LocationsRequest::new("https://rickandmortyapi.com")
+            .dimension("C-137")
+            .build_url();
+        let page = store.locations_page(&url).unwrap();
+        assert_eq!(page.results.len(), 1);
+        assert_eq!(page.results[0].name, "Earth (C-137)");
+
+        let url = EpisodesRequest::new("https://rickandmortyapi.com", vec![])
+            .name("nothing matches")
+            .build_url();
+        assert!(store.episodes_page(&url).is_err());
+    }
+
+    #[test]
+    fn selects_by_id() {
+        let store = store();
+        let url = EpisodesRequest::new("https://rickandmortyapi.com", vec![1]).build_url();
+        let page = store.episodes_page(&url).unwrap();
+        assert_eq!(page.results[0].name, "Pilot");
+    }
+}
@@ -1,22 +1,46 @@
-use std::error::Error;
 use async_trait::async_trait;
 pub mod entities;
+pub mod local_store;
 pub mod request;
 pub mod response;
 
-use self::entities::{CharacterPage, EpisodePage, LocationPage};
+use self::entities::{CharacterPage, EpisodePage, Info, LocationPage, Page};
+use super::error::RickError;
 
 #[async_trait]
 pub trait Rickuest {
-    async fn get_characters(self, url: String) -> Result<CharacterPage, Box<dyn Error>>;
-    async fn get_episodes(self, url: String) -> Result<EpisodePage, Box<dyn Error>>;
-    async fn get_locations(self, url: String) -> Result<LocationPage, Box<dyn Error>>;
+    async fn get_characters(self, url: String) -> Result<CharacterPage, RickError>;
+    async fn get_episodes(self, url: String) -> Result<EpisodePage, RickError>;
+    async fn get_locations(self, url: String) -> Result<LocationPage, RickError>;
+}
+
+/// Where the public API lives. The local mirror and the fixtures emulate it,
+/// so they build their URLs against it too.
+pub const DEFAULT_BASE_URL: &str = "https://rickandmortyapi.com";
+
+/// Message the API sends with a 404 when a filter or page matches nothing.
+pub const NOTHING_HERE: &str = "There is nothing here";
+
+/// No matches is not a failure, so the API's "nothing here" 404 becomes an
+/// empty page with zeroed Info. Every other error is passed through.
+pub fn empty_when_nothing_here<T>(
+    result: Result<Page<T>, RickError>,
+) -> Result<Page<T>, RickError> {
+    match result {
+        Err(RickError::Api {
+            status: 404,
+            message,
+        }) if message == NOTHING_HERE => Ok(Page {
+            info: Info::default(),
+            results: vec![],
+        }),
+        result => result,
+    }
 }
 
 // tests
 #[cfg(test)]
 mod tests {
-    use tokio;
 
     use super::request::builder::{CharactersRequest, RequestURLBuilder};
     use super::request::mock_request::MockRequest;
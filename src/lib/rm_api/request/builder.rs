@@ -1,9 +1,29 @@
-
+use crate::lib::query_language::{
+    operand::{Operand, OperandEnum},
+    operation::{OperationEnum, Root},
+    operation_list::OperationList,
+};
+use crate::lib::error::RickError;
 
 pub trait RequestURLBuilder {
     fn build_url(&self) -> String;
 }
 
+/// Sets a query parameter, replacing any previous value for the same key.
+fn set_param(parts: &mut Vec<String>, key: &str, value: String) {
+    let prefix = format!("{}=", key);
+    parts.retain(|part| !part.starts_with(&prefix));
+    parts.push(format!("{}{}", prefix, value));
+}
+
+/// Selects results by id, e.g `/api/episode/1,2?`, replacing any previous ids.
+fn set_ids(parts: &mut [String], ids: &[u32]) {
+    let path = parts[0].trim_end_matches('?');
+    let resource = &path[..path.rfind('/').map(|slash| slash + 1).unwrap_or(path.len())];
+    let ids = ids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    parts[0] = format!("{}{}?", resource, ids);
+}
+
 pub struct CharactersRequest(Vec<String>);
 
 impl CharactersRequest {
@@ -16,12 +36,17 @@ impl CharactersRequest {
     }
 
     pub fn name(&mut self, name: &str) -> &mut Self {
-        self.0.push(format!("name={}", name));
+        set_param(&mut self.0, "name", name.to_string());
         self
     }
 
     pub fn page(&mut self, page: u32) -> &mut Self {
-        self.0.push(format!("page={}", page));
+        set_param(&mut self.0, "page", page.to_string());
+        self
+    }
+
+    pub fn ids(&mut self, ids: &[u32]) -> &mut Self {
+        set_ids(&mut self.0, ids);
         self
     }
 }
@@ -48,12 +73,17 @@ impl EpisodesRequest {
     }
 
     pub fn name(&mut self, name: &str) -> &mut Self {
-        self.0.push(format!("name={}", name));
+        set_param(&mut self.0, "name", name.to_string());
         self
     }
 
     pub fn page(&mut self, page: u32) -> &mut Self {
-        self.0.push(format!("page={}", page));
+        set_param(&mut self.0, "page", page.to_string());
+        self
+    }
+
+    pub fn ids(&mut self, ids: &[u32]) -> &mut Self {
+        set_ids(&mut self.0, ids);
         self
     }
 }
@@ -70,17 +100,22 @@ impl LocationsRequest {
     }
 
     pub fn name(&mut self, name: &str) -> &mut Self {
-        self.0.push(format!("name={}", name));
+        set_param(&mut self.0, "name", name.to_string());
         self
     }
 
     pub fn page(&mut self, page: u32) -> &mut Self {
-        self.0.push(format!("page={}", page));
+        set_param(&mut self.0, "page", page.to_string());
+        self
+    }
+
+    pub fn ids(&mut self, ids: &[u32]) -> &mut Self {
+        set_ids(&mut self.0, ids);
         self
     }
 
     pub fn dimension(&mut self, dimension: &str) -> &mut Self {
-        self.0.push(format!("dimension={}", dimension));
+        set_param(&mut self.0, "dimension", dimension.to_string());
         self
     }
 }
@@ -102,6 +137,63 @@ impl RequestURLBuilder for LocationsRequest {
 }
 
 
+/// Builds the request url for an operation list, applying the operations the
+/// server understands. Other operations are only handled after the request is
+/// made, e.g the implementation of OperationListEvaluator on RMResponse.
+pub fn build_url_from_operations(
+    domain: &str,
+    operation_list: &OperationList,
+) -> Result<(Root, String), RickError> {
+    let root = match operation_list.first().map(|operation| &operation.0) {
+        Some(OperationEnum::Root(root)) => root.clone(),
+        _ => {
+            return Err(RickError::Parse(
+                "a query must start with CHARACTERS, EPISODES or LOCATIONS".to_string(),
+            ))
+        }
+    };
+    let mut characters = CharactersRequest::new(domain);
+    let mut episodes = EpisodesRequest::new(domain, vec![]);
+    let mut locations = LocationsRequest::new(domain);
+    for operation in operation_list.iter() {
+        match (&root, &operation.0) {
+            (Root::CHARACTERS, OperationEnum::Name(name)) => {
+                characters.name(String::from(name).as_str());
+            }
+            (Root::CHARACTERS, OperationEnum::Page(page)) => {
+                characters.page(page.into());
+            }
+            (Root::EPISODES, OperationEnum::Name(name)) => {
+                episodes.name(String::from(name).as_str());
+            }
+            (Root::EPISODES, OperationEnum::Page(page)) => {
+                episodes.page(page.into());
+            }
+            (Root::LOCATIONS, OperationEnum::Name(name)) => {
+                locations.name(String::from(name).as_str());
+            }
+            (Root::LOCATIONS, OperationEnum::Page(page)) => {
+                locations.page(page.into());
+            }
+            (Root::LOCATIONS, OperationEnum::Dimension(dimension)) => {
+                locations.dimension(String::from(dimension).as_str());
+            }
+            (_, OperationEnum::Ids(Operand(OperandEnum::NumberList(ids)))) => {
+                let ids: Vec<u32> = ids.iter().map(|id| *id as u32).collect();
+                characters.ids(&ids);
+                episodes.ids(&ids);
+                locations.ids(&ids);
+            }
+            _ => {}
+        }
+    }
+    let url = match root {
+        Root::CHARACTERS => characters.build_url(),
+        Root::EPISODES => episodes.build_url(),
+        Root::LOCATIONS => locations.build_url(),
+    };
+    Ok((root, url))
+}
 
 #[cfg(test)]
 mod tests {
@@ -159,4 +251,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn selects_by_ids() {
+        let url = CharactersRequest::new("https://rickandmortyapi.com")
+            .ids(&[1, 2])
+            .ids(&[3, 4])
+            .page(2)
+            .build_url();
+        assert_eq!(url, "https://rickandmortyapi.com/api/character/3,4?page=2");
+
+        let operation_list = OperationList::parse_str("EPISODES::IDS(1, 28)::LIMIT(1)").unwrap();
+        let (_, url) =
+            build_url_from_operations("https://rickandmortyapi.com", &operation_list).unwrap();
+        assert_eq!(url, "https://rickandmortyapi.com/api/episode/1,28?");
+    }
+
+    #[test]
+    fn test_build_url_from_operations() {
+        let operation_list =
+            OperationList::parse_str("LOCATIONS::NAME(Earth)::DIMENSION(C-137)::LIMIT(1)").unwrap();
+        let (root, url) =
+            build_url_from_operations("https://rickandmortyapi.com", &operation_list).unwrap();
+        assert_eq!(root, Root::LOCATIONS);
+        assert_eq!(
+            url,
+            "https://rickandmortyapi.com/api/location/?name=Earth&dimension=C-137"
+        );
+    }
+
 }
@@ -1,118 +1,285 @@
-use super::builder::{CharactersRequest, EpisodesRequest, LocationsRequest, RequestURLBuilder};
+use super::{
+    builder::build_url_from_operations, http_transport::HttpTransport, transport::Transport,
+};
+use crate::lib::error::RickError;
 use crate::lib::query_language::{
-    operation::{OperationEnum, Root},
+    operation::Root,
     operation_list::{OperationList, OperationListEvaluator},
 };
 use crate::lib::rm_api::{
+    empty_when_nothing_here,
     entities::*,
     response::{RMResponse, RMResponseEnum},
-    Rickuest,
+    Rickuest, DEFAULT_BASE_URL,
 };
 use async_trait::async_trait;
-use reqwest;
-use std::error::Error;
-pub struct DirectRequest;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::sync::Arc;
+/// Error body the API sends alongside non-success statuses.
+#[derive(Deserialize)]
+struct ApiError {
+    error: String,
+}
 
-#[async_trait]
-impl Rickuest for DirectRequest {
-    async fn get_characters(self, url: String) -> Result<CharacterPage, Box<dyn Error>> {
-        let req = reqwest::get(url.as_str()).await?;
-        let character_page: CharacterPage = req.json().await?;
+/// What the API answers `/api/<resource>/1,2` with, a list, or the result
+/// alone for a single id, instead of a page.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Selected<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+impl<T> From<Selected<T>> for Page<T> {
+    fn from(selected: Selected<T>) -> Self {
+        let results = match selected {
+            Selected::Many(results) => results,
+            Selected::One(result) => vec![result],
+        };
+        Page {
+            info: Info {
+                count: results.len() as u32,
+                pages: 1,
+                next: None,
+                prev: None,
+            },
+            results,
+        }
+    }
+}
+
+/// Whether a url selects results by id, e.g `/api/episode/1,2`.
+fn selects_ids(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| Some(url.path_segments()?.nth(2)?.to_string()))
+        .is_some_and(|ids| !ids.is_empty())
+}
+
+#[derive(Clone)]
+pub struct DirectRequest {
+    transport: Arc<dyn Transport>,
+    base_url: String,
+}
 
-        Ok(character_page)
+impl DirectRequest {
+    pub fn new() -> Self {
+        Self::with_transport(Arc::new(HttpTransport::default()))
     }
 
-    async fn get_episodes(self, url: String) -> Result<EpisodePage, Box<dyn Error>> {
-        let req = reqwest::get(url.as_str()).await?;
-        let episode_page: EpisodePage = req.json().await?;
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        DirectRequest {
+            transport,
+            base_url: DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Sends requests to another server speaking the API, e.g a self hosted copy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T, RickError> {
+        let response = self.transport.get(url).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(match serde_json::from_str::<ApiError>(&response.body) {
+                Ok(api_error) => RickError::Api {
+                    status: response.status,
+                    message: api_error.error,
+                },
+                Err(_) => RickError::HttpStatus {
+                    status: response.status,
+                    url: url.to_string(),
+                },
+            });
+        }
+        Ok(serde_json::from_str(&response.body)?)
+    }
+
+    async fn fetch_page<T: DeserializeOwned>(&self, url: &str) -> Result<Page<T>, RickError> {
+        if selects_ids(url) {
+            return Ok(self.fetch::<Selected<T>>(url).await?.into());
+        }
+        self.fetch(url).await
+    }
+}
+
+impl Default for DirectRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        Ok(episode_page)
+#[async_trait]
+impl Rickuest for DirectRequest {
+    async fn get_characters(self, url: String) -> Result<CharacterPage, RickError> {
+        empty_when_nothing_here(self.fetch_page(url.as_str()).await)
     }
 
-    async fn get_locations(self, url: String) -> Result<LocationPage, Box<dyn Error>> {
-        let req = reqwest::get(url.as_str()).await?;
-        let location_page: LocationPage = req.json().await?;
+    async fn get_episodes(self, url: String) -> Result<EpisodePage, RickError> {
+        empty_when_nothing_here(self.fetch_page(url.as_str()).await)
+    }
 
-        Ok(location_page)
+    async fn get_locations(self, url: String) -> Result<LocationPage, RickError> {
+        empty_when_nothing_here(self.fetch_page(url.as_str()).await)
     }
 }
 
 #[async_trait]
 impl OperationListEvaluator for DirectRequest {
-    async fn evaluate_op(
-        &self,
-        operation_list: &OperationList,
-    ) -> Result<RMResponse, Box<dyn Error>> {
-        let result = match operation_list[0].0 {
-            OperationEnum::Root(Root::CHARACTERS) => {
-                let mut builder = CharactersRequest::new("https://rickandmortyapi.com");
-                for operation in operation_list.0.iter() {
-                    match &operation.0 {
-                        OperationEnum::Name(name) => {
-                            let name: String = name.into();
-                            builder.name(name.as_str());
-                        }
-                        OperationEnum::Page(page) => {
-                            let page_number: u32 = page.into();
-                            builder.page(page_number);
-                        }
-                        _ => {} // other operations are only handled after the request is made. e.g the implementation of OperationListEvaluator on RMResponse
-                    };
-                }
-                let url = builder.build_url();
-                DirectRequest
-                    .get_characters(url)
-                    .await
-                    .map(|response| RMResponse(RMResponseEnum::Characters(response)))
-            }
-            OperationEnum::Root(Root::EPISODES) => {
-                let mut builder = EpisodesRequest::new("https://rickandmortyapi.com", vec![]);
-                for operation in operation_list.0.iter() {
-                    match &operation.0 {
-                        OperationEnum::Name(name) => {
-                            let name: String = name.into();
-                            builder.name(name.as_str());
-                        }
-                        OperationEnum::Page(page) => {
-                            let page_number: u32 = page.into();
-                            builder.page(page_number);
-                        }
-                        _ => {} // other operations are only handled after the request is made. e.g the implementation of OperationListEvaluator on RMResponse
-                    };
-                }
-                let url = builder.build_url();
-                DirectRequest
-                    .get_episodes(url)
-                    .await
-                    .map(|response| RMResponse(RMResponseEnum::Episodes(response)))
+    async fn evaluate_op(&self, operation_list: &OperationList) -> Result<RMResponse, RickError> {
+        let (root, url) = build_url_from_operations(&self.base_url, operation_list)?;
+        match root {
+            Root::CHARACTERS => self
+                .clone()
+                .get_characters(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Characters(response))),
+            Root::EPISODES => self
+                .clone()
+                .get_episodes(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Episodes(response))),
+            Root::LOCATIONS => self
+                .clone()
+                .get_locations(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Locations(response))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::request::transport::{ReplayTransport, TransportResponse};
+    use std::path::Path;
+
+    struct StatusTransport(u16, &'static str);
+
+    #[async_trait]
+    impl Transport for StatusTransport {
+        async fn get(&self, _url: &str) -> Result<TransportResponse, RickError> {
+            Ok(TransportResponse {
+                status: self.0,
+                body: self.1.to_string(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_replayed_payloads() {
+        let transport = ReplayTransport::load(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/lib/rm_api/request/fixtures/cassettes/characters_name_rick.jsonl"
+        )))
+        .unwrap();
+        let request = DirectRequest::with_transport(Arc::new(transport));
+
+        let operation_list = OperationList::parse_str("CHARACTERS::NAME(rick)").unwrap();
+        let response = request.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Characters(page) => {
+                assert_eq!(page.info.count, 5);
+                assert_eq!(page.results[0].name, "Rick Sanchez");
             }
-            OperationEnum::Root(Root::LOCATIONS) => {
-                let mut builder = LocationsRequest::new("https://rickandmortyapi.com");
-                for operation in operation_list.0.iter() {
-                    match &operation.0 {
-                        OperationEnum::Name(name) => {
-                            let name: String = name.into();
-                            builder.name(name.as_str());
-                        }
-                        OperationEnum::Page(page) => {
-                            let page_number: u32 = page.into();
-                            builder.page(page_number);
-                        }
-                        OperationEnum::Dimension(dimension) => {
-                            let dimension: String = dimension.into();
-                            builder.dimension(dimension.as_str());
-                        }
-                        _ => {} // other operations are only handled after the request is made. e.g the implementation of OperationListEvaluator on RMResponse
-                    };
-                }
-                let url = builder.build_url();
-                DirectRequest
-                    .get_locations(url)
-                    .await
-                    .map(|response| RMResponse(RMResponseEnum::Locations(response)))
+            _ => panic!(),
+        }
+
+        let operation_list = OperationList::parse_str("CHARACTERS::NAME(morty)").unwrap();
+        assert!(matches!(
+            request.evaluate_op(&operation_list).await,
+            Err(RickError::NotRecorded(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_requests_to_the_base_url() {
+        let operation_list = OperationList::parse_str("EPISODES::NAME(pilot)").unwrap();
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(500, "")))
+            .with_base_url("http://localhost:8080/");
+        match request.evaluate_op(&operation_list).await {
+            Err(RickError::HttpStatus { url, .. }) => {
+                assert!(url.starts_with("http://localhost:8080/api/episode"), "{}", url);
             }
-            _ => Err("Invalid Root".into()),
+            result => panic!("unexpected {:?}", result.map(|response| response.0)),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_results_selected_by_id() {
+        let episode = |id, name: &str| Episode {
+            id,
+            name: name.into(),
+            ..Default::default()
         };
-        result
+        let both = serde_json::to_string(&[episode(1, "Pilot"), episode(2, "Lawnmower Dog")]);
+        let operation_list = OperationList::parse_str("EPISODES::IDS(1, 2)").unwrap();
+        let request =
+            DirectRequest::with_transport(Arc::new(StatusTransport(200, both.unwrap().leak())));
+        match request.evaluate_op(&operation_list).await.unwrap().0 {
+            RMResponseEnum::Episodes(page) => {
+                assert_eq!(page.info.count, 2);
+                assert_eq!(page.results[1].name, "Lawnmower Dog");
+            }
+            _ => panic!(),
+        }
+
+        let operation_list = OperationList::parse_str("EPISODES::IDS(1)").unwrap();
+        let one = serde_json::to_string(&episode(1, "Pilot")).unwrap();
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(200, one.leak())));
+        match request.evaluate_op(&operation_list).await.unwrap().0 {
+            RMResponseEnum::Episodes(page) => assert_eq!(page.results[0].name, "Pilot"),
+            _ => panic!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn distinguishes_error_responses() {
+        let operation_list = OperationList::parse_str("CHARACTERS").unwrap();
+
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(
+            404,
+            r#"{"error": "There is nothing here"}"#,
+        )));
+        match request.evaluate_op(&operation_list).await.unwrap().0 {
+            RMResponseEnum::Characters(page) => {
+                assert_eq!(page.info, Info::default());
+                assert!(page.results.is_empty());
+            }
+            _ => panic!(),
+        }
+
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(
+            404,
+            r#"{"error": "Character not found"}"#,
+        )));
+        assert_eq!(
+            request.evaluate_op(&operation_list).await.err(),
+            Some(RickError::Api {
+                status: 404,
+                message: "Character not found".to_string()
+            })
+        );
+
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(
+            502,
+            "<html>Bad Gateway</html>",
+        )));
+        assert!(matches!(
+            request.evaluate_op(&operation_list).await,
+            Err(RickError::HttpStatus { status: 502, .. })
+        ));
+
+        let request = DirectRequest::with_transport(Arc::new(StatusTransport(200, "{}")));
+        assert!(matches!(
+            request.evaluate_op(&operation_list).await,
+            Err(RickError::Decode(_))
+        ));
     }
 }
@@ -0,0 +1,310 @@
+use super::transport::{Transport, TransportResponse};
+use crate::lib::error::RickError;
+use async_trait::async_trait;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct HttpConfig {
+    /// Limit for a whole request, from connecting to reading the body
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub user_agent: String,
+    /// Route every request through this proxy, e.g `http://localhost:8080`
+    pub proxy: Option<String>,
+    /// How many times a transient failure is retried before giving up
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every following one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Longest `Retry-After` waited for, a server asking for more gets its
+    /// response returned instead of a retry
+    pub max_retry_after: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            user_agent: format!("rick_cli/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(8),
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Talks to the network through one pooled client, retrying transient
+/// failures with exponential backoff and honouring `Retry-After`.
+pub struct HttpTransport {
+    client: reqwest::Client,
+    config: HttpConfig,
+}
+
+impl HttpTransport {
+    pub fn new(config: HttpConfig) -> Result<Self, RickError> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .user_agent(config.user_agent.as_str());
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+        Ok(HttpTransport {
+            client: builder.build()?,
+            config,
+        })
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.max_backoff)
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        HttpTransport::new(HttpConfig::default()).expect("default http client should build")
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Reads `Retry-After` as either delay seconds or an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+impl HttpTransport {
+    /// Sends a GET, retrying transient failures, and returns the last response.
+    async fn send(&self, url: &str) -> Result<reqwest::Response, RickError> {
+        let mut attempt = 0;
+        loop {
+            let retries_left = attempt < self.config.max_retries;
+            match self.client.get(url).send().await {
+                Ok(response) if retries_left && is_transient(response.status()) => {
+                    // the server's delay is waited in full, retrying earlier
+                    // only spends the retries against its rate limit
+                    let delay = match retry_after(&response) {
+                        Some(delay) if delay > self.config.max_retry_after => return Ok(response),
+                        Some(delay) => delay,
+                        None => self.backoff(attempt),
+                    };
+                    tokio::time::sleep(delay).await;
+                }
+                Ok(response) => return Ok(response),
+                Err(err) if retries_left && (err.is_timeout() || err.is_connect()) => {
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+        let response = self.send(url).await?;
+        let status = response.status().as_u16();
+        let body = response.text().await?;
+        Ok(TransportResponse { status, body })
+    }
+
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, RickError> {
+        let response = self.send(url).await?;
+        if !response.status().is_success() {
+            return Err(RickError::HttpStatus {
+                status: response.status().as_u16(),
+                url: url.to_string(),
+            });
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Serves the given raw responses in order, one per connection, and
+    /// counts how many requests arrived.
+    async fn stub_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 1024];
+                let _ = socket.read(&mut buffer).await;
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.unwrap();
+            }
+        });
+        (address, hits)
+    }
+
+    fn test_config() -> HttpConfig {
+        HttpConfig {
+            timeout: Duration::from_millis(500),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+
+    #[tokio::test]
+    async fn retries_transient_statuses() {
+        let (address, hits) = stub_server(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            OK,
+        ])
+        .await;
+        let transport = HttpTransport::new(test_config()).unwrap();
+
+        let response = transport.get(&address).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "{}");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after_beyond_the_backoff_limit() {
+        let (address, hits) = stub_server(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            OK,
+        ])
+        .await;
+        let transport = HttpTransport::new(test_config()).unwrap();
+
+        let started_at = std::time::Instant::now();
+        let response = transport.get(&address).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert!(started_at.elapsed() >= Duration::from_secs(1));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn returns_the_429_when_retry_after_is_too_long() {
+        let (address, hits) = stub_server(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            OK,
+        ])
+        .await;
+        let transport = HttpTransport::new(HttpConfig {
+            max_retry_after: Duration::from_secs(5),
+            ..test_config()
+        })
+        .unwrap();
+
+        let started_at = std::time::Instant::now();
+        let response = transport.get(&address).await.unwrap();
+        assert_eq!(response.status, 429);
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (address, hits) = stub_server(vec![
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+        let transport = HttpTransport::new(HttpConfig {
+            max_retries: 1,
+            ..test_config()
+        })
+        .unwrap();
+
+        let response = transport.get(&address).await.unwrap();
+        assert_eq!(response.status, 500);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn downloads_bytes() {
+        let (address, _) = stub_server(vec![
+            OK,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+        let transport = HttpTransport::new(test_config()).unwrap();
+
+        assert_eq!(transport.get_bytes(&address).await.unwrap(), b"{}");
+        assert!(matches!(
+            transport.get_bytes(&address).await,
+            Err(RickError::HttpStatus { status: 404, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_on_silent_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let transport = HttpTransport::new(HttpConfig {
+            timeout: Duration::from_millis(50),
+            max_retries: 0,
+            ..test_config()
+        })
+        .unwrap();
+
+        assert!(matches!(
+            transport.get(&address).await,
+            Err(RickError::Network(_))
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let transport = HttpTransport::new(HttpConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(transport.backoff(0), Duration::from_millis(100));
+        assert_eq!(transport.backoff(1), Duration::from_millis(200));
+        assert_eq!(transport.backoff(2), Duration::from_millis(350));
+    }
+}
@@ -0,0 +1,95 @@
+use super::builder::build_url_from_operations;
+use crate::lib::error::RickError;
+use crate::lib::query_language::{
+    operation::Root,
+    operation_list::{OperationList, OperationListEvaluator},
+};
+use crate::lib::rm_api::{
+    empty_when_nothing_here,
+    entities::*,
+    local_store::LocalStore,
+    response::{RMResponse, RMResponseEnum},
+    Rickuest, DEFAULT_BASE_URL,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Answers queries entirely from a mirror downloaded by `rick_cli sync`.
+#[derive(Clone)]
+pub struct LocalRequest(pub Arc<LocalStore>);
+
+#[async_trait]
+impl Rickuest for LocalRequest {
+    async fn get_characters(self, url: String) -> Result<CharacterPage, RickError> {
+        empty_when_nothing_here(self.0.characters_page(&url))
+    }
+
+    async fn get_episodes(self, url: String) -> Result<EpisodePage, RickError> {
+        empty_when_nothing_here(self.0.episodes_page(&url))
+    }
+
+    async fn get_locations(self, url: String) -> Result<LocationPage, RickError> {
+        empty_when_nothing_here(self.0.locations_page(&url))
+    }
+}
+
+#[async_trait]
+impl OperationListEvaluator for LocalRequest {
+    async fn evaluate_op(&self, operation_list: &OperationList) -> Result<RMResponse, RickError> {
+        let (root, url) = build_url_from_operations(DEFAULT_BASE_URL, operation_list)?;
+        match root {
+            Root::CHARACTERS => self
+                .clone()
+                .get_characters(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Characters(response))),
+            Root::EPISODES => self
+                .clone()
+                .get_episodes(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Episodes(response))),
+            Root::LOCATIONS => self
+                .clone()
+                .get_locations(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Locations(response))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_queries_from_mirror() {
+        let store = LocalStore {
+            locations: vec![
+                Location {
+                    id: 1,
+                    name: "Earth (C-137)".into(),
+                    dimension: "Dimension C-137".into(),
+                    ..Default::default()
+                },
+                Location {
+                    id: 3,
+                    name: "Citadel of Ricks".into(),
+                    dimension: "unknown".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let request = LocalRequest(Arc::new(store));
+        let operation_list = OperationList::parse_str("LOCATIONS::DIMENSION(C-137)").unwrap();
+        let response = request.evaluate_op(&operation_list).await.unwrap();
+
+        match response.0 {
+            RMResponseEnum::Locations(page) => {
+                assert_eq!(page.info.count, 1);
+                assert_eq!(page.results[0].name, "Earth (C-137)");
+            }
+            _ => panic!(),
+        }
+    }
+}
@@ -1,83 +1,145 @@
-use super::builder::{CharactersRequest, RequestURLBuilder};
+use super::builder::build_url_from_operations;
+use crate::lib::error::RickError;
+use crate::lib::query_language::{
+    operation::Root,
+    operation_list::{OperationList, OperationListEvaluator},
+};
 use crate::lib::rm_api::{
+    empty_when_nothing_here,
     entities::*,
+    local_store::LocalStore,
     response::{RMResponse, RMResponseEnum},
-    Rickuest,
-};
-use crate::lib::query_language::{
-    operation::{OperationEnum, Root},
-    operation_list::{OperationList, OperationListEvaluator},
-    *,
+    Rickuest, DEFAULT_BASE_URL,
 };
 use async_trait::async_trait;
 
-use std::error::Error;
+use std::sync::OnceLock;
 
+/// Serves the JSON fixtures in `fixtures/`, which are shaped like the real API
+/// responses, honouring the same url parameters the upstream server does.
+#[derive(Clone)]
 pub struct MockRequest;
 
+impl MockRequest {
+    pub fn fixtures() -> &'static LocalStore {
+        static FIXTURES: OnceLock<LocalStore> = OnceLock::new();
+        FIXTURES.get_or_init(|| LocalStore {
+            characters: serde_json::from_str(include_str!("fixtures/characters.json"))
+                .expect("characters fixture should be valid"),
+            locations: serde_json::from_str(include_str!("fixtures/locations.json"))
+                .expect("locations fixture should be valid"),
+            episodes: serde_json::from_str(include_str!("fixtures/episodes.json"))
+                .expect("episodes fixture should be valid"),
+        })
+    }
+}
+
 #[async_trait]
 impl OperationListEvaluator for MockRequest {
-    async fn evaluate_op(
-        &self,
-        operation_list: &OperationList,
-    ) -> Result<RMResponse, Box<dyn Error>> {
-        let result = match operation_list[0].0 {
-            OperationEnum::Root(Root::CHARACTERS) => {
-                let mut builder = CharactersRequest::new("https://rickandmortyapi.com");
-                for operation in operation_list.0.iter() {
-                    match &operation.0 {
-                        OperationEnum::Name(name) => {
-                            let name: String = name.into();
-                            builder.name(name.as_str());
-                        }
-                        OperationEnum::Page(page) => {
-                            let page_number: u32 = page.into();
-                            // builder.page(page);
-                        }
-                        _ => {} // other operations are only handled after the request is made. e.g the implementation of OperationListEvaluator on RMResponse
-                    };
-                }
-                let url = builder.build_url();
-                MockRequest
-                    .get_characters(url)
-                    .await
-                    .map(|response| RMResponse(RMResponseEnum::Characters(response)))
-            }
-            _ => Err("Invalid Root".into()),
-        };
-        result
+    async fn evaluate_op(&self, operation_list: &OperationList) -> Result<RMResponse, RickError> {
+        let (root, url) = build_url_from_operations(DEFAULT_BASE_URL, operation_list)?;
+        match root {
+            Root::CHARACTERS => MockRequest
+                .get_characters(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Characters(response))),
+            Root::EPISODES => MockRequest
+                .get_episodes(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Episodes(response))),
+            Root::LOCATIONS => MockRequest
+                .get_locations(url)
+                .await
+                .map(|response| RMResponse(RMResponseEnum::Locations(response))),
+        }
     }
 }
 
-
-
 #[async_trait]
 impl Rickuest for MockRequest {
-    async fn get_characters(self, url: String) -> Result<CharacterPage, Box<dyn Error>> {
-        let character_page = CharacterPage {
-            info: Info {
-                count: 1,
-                pages: 1,
-                next: None,
-                prev: None,
-            },
-            results: vec![Character {
-                id: 1,
-                name: "Rick Sanchez".to_string(),
-                status: "Alive".to_string(),
-                species: "Human".to_string(),
-                _type: "Genius".to_string(),
-                ..Default::default()
-            }],
-        };
-        Ok(character_page)
+    async fn get_characters(self, url: String) -> Result<CharacterPage, RickError> {
+        empty_when_nothing_here(MockRequest::fixtures().characters_page(&url))
     }
 
-    async fn get_episodes(self, url: String) -> Result<EpisodePage, Box<dyn Error>> {
-        Ok(Default::default())
+    async fn get_episodes(self, url: String) -> Result<EpisodePage, RickError> {
+        empty_when_nothing_here(MockRequest::fixtures().episodes_page(&url))
     }
 
-    async fn get_locations(self, url: String) -> Result<LocationPage, Box<dyn Error>> {
-        Ok(Default::default())
+    async fn get_locations(self, url: String) -> Result<LocationPage, RickError> {
+        empty_when_nothing_here(MockRequest::fixtures().locations_page(&url))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::request::builder::{EpisodesRequest, RequestURLBuilder};
+
+    #[tokio::test]
+    async fn paginates_characters_fixture() {
+        let operation_list = OperationList::parse_str("CHARACTERS").unwrap();
+        let response = MockRequest.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Characters(page) => {
+                assert_eq!(page.info.count, 25);
+                assert_eq!(page.info.pages, 2);
+                assert_eq!(page.results.len(), 20);
+                assert_eq!(
+                    page.info.next.as_deref(),
+                    Some("https://rickandmortyapi.com/api/character/?page=2")
+                );
+            }
+            _ => panic!(),
+        }
+
+        let operation_list = OperationList::parse_str("CHARACTERS::PAGE(2)").unwrap();
+        let response = MockRequest.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Characters(page) => {
+                assert_eq!(page.results.len(), 5);
+                assert_eq!(page.results[0].name, "Aqua Morty");
+                assert_eq!(page.info.next, None);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_episodes_and_locations() {
+        let operation_list = OperationList::parse_str("EPISODES::NAME(rick)").unwrap();
+        let response = MockRequest.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Episodes(page) => {
+                assert_eq!(page.info.count, 4);
+            }
+            _ => panic!(),
+        }
+
+        let operation_list = OperationList::parse_str("LOCATIONS::DIMENSION(C-137)").unwrap();
+        let response = MockRequest.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Locations(page) => {
+                assert_eq!(page.info.count, 2);
+            }
+            _ => panic!(),
+        }
+
+        let operation_list = OperationList::parse_str("CHARACTERS::NAME(xxxxxx)").unwrap();
+        let response = MockRequest.evaluate_op(&operation_list).await.unwrap();
+        match response.0 {
+            RMResponseEnum::Characters(page) => {
+                assert_eq!(page.info, Info::default());
+                assert!(page.results.is_empty());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn selects_episodes_by_id() {
+        let url = EpisodesRequest::new("https://rickandmortyapi.com", vec![1, 3]).build_url();
+        let page = MockRequest.get_episodes(url).await.unwrap();
+        assert_eq!(page.results.len(), 2);
+        assert_eq!(page.results[1].name, "Anatomy Park");
+    }
+}
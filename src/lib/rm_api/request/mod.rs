@@ -1,7 +1,10 @@
 
 pub mod builder;
 pub mod mock_request;
+pub mod transport;
 pub mod direct_request;
+pub mod http_transport;
+pub mod local_request;
 
 // tests
 #[cfg(test)]
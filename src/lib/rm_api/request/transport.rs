@@ -0,0 +1,294 @@
+use crate::lib::error::RickError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+tokio::task_local! {
+    /// Counts the responses CachingTransport served from memory while a
+    /// query runs, see `query_api`
+    pub static CACHE_HITS: Cell<u32>;
+}
+
+/// The raw outcome of an HTTP GET, before any decoding.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TransportResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Moves bytes for DirectRequest, so the network can be swapped for recorded
+/// traffic.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn get(&self, url: &str) -> Result<TransportResponse, RickError>;
+
+    /// Downloads a binary resource such as a portrait. Transports that only
+    /// hold JSON, like a replayed cassette, cannot.
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, RickError> {
+        Err(RickError::NotRecorded(url.to_string()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Interaction {
+    pub url: String,
+    pub response: TransportResponse,
+}
+
+/// Request/response pairs persisted as JSON lines, one interaction per line.
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self, RickError> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            RickError::Io(format!(
+                "could not read cassette {} ({})",
+                path.display(),
+                err
+            ))
+        })?;
+        let interactions = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(Cassette { interactions })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RickError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = String::new();
+        for interaction in &self.interactions {
+            contents.push_str(&serde_json::to_string(interaction)?);
+            contents.push('\n');
+        }
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Forwards requests to another transport and appends every exchange to a
+/// cassette file as it happens.
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    /// Held while a line is written, so concurrent queries do not interleave
+    file: Mutex<File>,
+}
+
+impl RecordingTransport {
+    /// Starts an empty cassette at `path`, replacing any earlier recording.
+    pub fn new(inner: Arc<dyn Transport>, path: &Path) -> Result<Self, RickError> {
+        Cassette::default().save(path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            inner,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+        let response = self.inner.get(url).await?;
+        let line = serde_json::to_string(&Interaction {
+            url: url.to_string(),
+            response: response.clone(),
+        })?;
+        writeln!(self.file.lock().unwrap(), "{}", line)?;
+        Ok(response)
+    }
+
+    /// Images are not API traffic, so they are passed through unrecorded
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, RickError> {
+        self.inner.get_bytes(url).await
+    }
+}
+
+/// Serves responses from a cassette and fails on any request it has not seen.
+pub struct ReplayTransport {
+    cassette: Cassette,
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: Cassette) -> Self {
+        Self { cassette }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RickError> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+        self.cassette
+            .interactions
+            .iter()
+            .find(|interaction| interaction.url == url)
+            .map(|interaction| interaction.response.clone())
+            .ok_or_else(|| RickError::NotRecorded(url.to_string()))
+    }
+}
+
+/// Keeps responses in memory for `ttl`, so repeating a query or paging
+/// back does not go to the network again.
+pub struct CachingTransport {
+    inner: Arc<dyn Transport>,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, TransportResponse)>>,
+    downloads: Mutex<HashMap<String, (Instant, Vec<u8>)>>,
+}
+
+impl CachingTransport {
+    pub fn new(inner: Arc<dyn Transport>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            downloads: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for CachingTransport {
+    async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+        if let Some((stored_at, response)) = self.entries.lock().unwrap().get(url) {
+            if stored_at.elapsed() < self.ttl {
+                // outside a query there is nobody counting
+                let _ = CACHE_HITS.try_with(|hits| hits.set(hits.get() + 1));
+                return Ok(response.clone());
+            }
+        }
+        let response = self.inner.get(url).await?;
+        // rate limits and server errors are worth asking again for
+        if response.status < 500 && response.status != 429 {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), (Instant::now(), response.clone()));
+        }
+        Ok(response)
+    }
+
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, RickError> {
+        if let Some((stored_at, bytes)) = self.downloads.lock().unwrap().get(url) {
+            if stored_at.elapsed() < self.ttl {
+                return Ok(bytes.clone());
+            }
+        }
+        let bytes = self.inner.get_bytes(url).await?;
+        self.downloads
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), (Instant::now(), bytes.clone()));
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticTransport;
+
+    #[async_trait]
+    impl Transport for StaticTransport {
+        async fn get(&self, url: &str) -> Result<TransportResponse, RickError> {
+            Ok(TransportResponse {
+                status: 200,
+                body: format!("{{\"url\": \"{}\"}}", url),
+            })
+        }
+
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, RickError> {
+            Ok(url.as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_what_was_recorded() {
+        let path =
+            std::env::temp_dir().join(format!("rick_cli_cassette_{}.jsonl", std::process::id()));
+        let recorder = RecordingTransport::new(Arc::new(StaticTransport), &path).unwrap();
+        let recorded = recorder
+            .get("https://rickandmortyapi.com/api/character/?")
+            .await
+            .unwrap();
+        recorder
+            .get("https://rickandmortyapi.com/api/location/?")
+            .await
+            .unwrap();
+        // one interaction per line, appended as each request finishes
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let replay = ReplayTransport::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let replayed = replay
+            .get("https://rickandmortyapi.com/api/character/?")
+            .await
+            .unwrap();
+        assert_eq!(recorded, replayed);
+        assert_eq!(
+            replay
+                .get("https://rickandmortyapi.com/api/episode/?")
+                .await,
+            Err(RickError::NotRecorded(
+                "https://rickandmortyapi.com/api/episode/?".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_cache_hits() {
+        let cache = CachingTransport::new(Arc::new(StaticTransport), Duration::from_secs(60));
+        let hits = CACHE_HITS
+            .scope(Cell::new(0), async {
+                let first = cache.get("https://rickandmortyapi.com/api/episode/").await;
+                let second = cache.get("https://rickandmortyapi.com/api/episode/").await;
+                assert_eq!(first.unwrap(), second.unwrap());
+                CACHE_HITS.with(Cell::get)
+            })
+            .await;
+        assert_eq!(hits, 1);
+
+        let expired = CachingTransport::new(Arc::new(StaticTransport), Duration::ZERO);
+        let hits = CACHE_HITS
+            .scope(Cell::new(0), async {
+                expired.get("https://rickandmortyapi.com/api/episode/").await.unwrap();
+                expired.get("https://rickandmortyapi.com/api/episode/").await.unwrap();
+                CACHE_HITS.with(Cell::get)
+            })
+            .await;
+        assert_eq!(hits, 0);
+    }
+
+    #[tokio::test]
+    async fn caches_downloads_but_cannot_replay_them() {
+        let cache = CachingTransport::new(Arc::new(StaticTransport), Duration::from_secs(60));
+        let url = "https://rickandmortyapi.com/api/character/avatar/1.jpeg";
+        assert_eq!(cache.get_bytes(url).await.unwrap(), url.as_bytes());
+        assert_eq!(cache.downloads.lock().unwrap().len(), 1);
+
+        let replay = ReplayTransport::new(Cassette::default());
+        assert!(matches!(
+            replay.get_bytes(url).await,
+            Err(RickError::NotRecorded(_))
+        ));
+    }
+}
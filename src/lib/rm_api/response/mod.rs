@@ -1,9 +1,15 @@
 use async_trait::async_trait;
 
-use crate::lib::query_language::{operation::*, operation_list::*};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use crate::lib::{
+    error::RickError,
+    query_language::{fields::*, operation::*, operation_list::*},
+};
+use chrono::{DateTime, NaiveDate};
 
-use super::entities::{CharacterPage, EpisodePage, LocationPage};
+use super::entities::{Character, CharacterPage, Episode, EpisodePage, Info, Location, LocationPage};
+
+/// How the API writes an episode's air date, e.g `December 2, 2013`
+const AIR_DATE_FORMAT: &str = "%B %-d, %Y";
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum RMResponseEnum {
@@ -12,6 +18,25 @@ pub enum RMResponseEnum {
     Locations(LocationPage),
 }
 
+impl RMResponseEnum {
+    pub fn info(&self) -> &Info {
+        match self {
+            RMResponseEnum::Characters(page) => &page.info,
+            RMResponseEnum::Episodes(page) => &page.info,
+            RMResponseEnum::Locations(page) => &page.info,
+        }
+    }
+
+    /// The number of results on this page
+    pub fn result_count(&self) -> usize {
+        match self {
+            RMResponseEnum::Characters(page) => page.results.len(),
+            RMResponseEnum::Episodes(page) => page.results.len(),
+            RMResponseEnum::Locations(page) => page.results.len(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RMResponse(pub RMResponseEnum);
 
@@ -20,7 +45,7 @@ impl OperationListEvaluator for RMResponse {
     async fn evaluate_op(
         &self,
         operation_list: &OperationList,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
+    ) -> Result<Self, RickError> {
         let result = match &self.0 {
             RMResponseEnum::Locations(page) => {
                 let mut new_page = page.clone();
@@ -29,25 +54,37 @@ impl OperationListEvaluator for RMResponse {
                         OperationEnum::Contains(field_name, field_value) => {
                             let field_name: String = field_name.into();
                             let field_value_check: String = field_value.into();
+                            LOCATION_FIELDS.check_searchable(&field_name)?;
                             let new_page_result = new_page
                                 .results
                                 .iter()
                                 .filter(|&result| {
                                     let field_value = match field_name.as_str() {
-                                        "name" => &result.name,
-                                        "type" => &result._type,
-                                        "dimension" => &result.dimension,
-                                        _ => "",
+                                        "id" => result.id.to_string(),
+                                        "name" => result.name.clone(),
+                                        "type" => result._type.clone(),
+                                        "dimension" => result.dimension.clone(),
+                                        _ => unreachable!("field names are checked above"),
                                     };
                                     field_value.contains(&field_value_check)
                                 })
-                                .map(|result| result.clone())
+                                .cloned()
                                 .collect::<Vec<_>>();
                             new_page.results = new_page_result;
                         }
                         OperationEnum::Sort(sort_direction, field_name) => {
                             let field_name: String = field_name.into();
                             let sort_direction: String = sort_direction.into();
+                            LOCATION_FIELDS.check_sortable(&field_name)?;
+                            let created = |location: &Location| {
+                                DateTime::parse_from_rfc3339(&location.created).ok()
+                            };
+                            if field_name == "created" {
+                                check_created_dates(
+                                    "location",
+                                    new_page.results.iter().map(|location| (location.id, location.created.as_str())),
+                                )?;
+                            }
                             match sort_direction.as_str() {
                                 "ASC" => {
                                     new_page.results.sort_by(|a, b| match field_name.as_str() {
@@ -56,12 +93,8 @@ impl OperationListEvaluator for RMResponse {
                                         "dimension" => a.dimension.cmp(&b.dimension),
                                         "residents" => a.residents.cmp(&b.residents),
                                         "id" => a.id.cmp(&b.id),
-                                        "created" => {
-                                            DateTime::parse_from_rfc3339(&a.created).unwrap().cmp(
-                                                &DateTime::parse_from_rfc3339(&b.created).unwrap(),
-                                            )
-                                        }
-                                        _ => a.name.cmp(&b.name),
+                                        "created" => created(a).cmp(&created(b)),
+                                        _ => unreachable!("field names are checked above"),
                                     })
                                 }
                                 "DSC" => {
@@ -71,12 +104,8 @@ impl OperationListEvaluator for RMResponse {
                                         "dimension" => b.dimension.cmp(&a.dimension),
                                         "residents" => b.residents.cmp(&a.residents),
                                         "id" => b.id.cmp(&a.id),
-                                        "created" => {
-                                            DateTime::parse_from_rfc3339(&b.created).unwrap().cmp(
-                                                &DateTime::parse_from_rfc3339(&a.created).unwrap(),
-                                            )
-                                        }
-                                        _ => b.name.cmp(&a.name),
+                                        "created" => created(b).cmp(&created(a)),
+                                        _ => unreachable!("field names are checked above"),
                                     });
                                 }
                                 _ => unreachable!("sort direction should be ASC or DSC"),
@@ -103,21 +132,23 @@ impl OperationListEvaluator for RMResponse {
                         OperationEnum::Contains(field_name, field_value) => {
                             let field_name: String = field_name.into();
                             let field_value_check: String = field_value.into();
+                            CHARACTER_FIELDS.check_searchable(&field_name)?;
                             let new_page_result = new_page
                                 .results
                                 .iter()
                                 .filter(|&result| {
                                     let field_value = match field_name.as_str() {
-                                        "name" => &result.name,
-                                        "status" => &result.status,
-                                        "species" => &result.species,
-                                        "type" => &result._type,
-                                        "gender" => &result.gender,
-                                        _ => "",
+                                        "id" => result.id.to_string(),
+                                        "name" => result.name.clone(),
+                                        "status" => result.status.clone(),
+                                        "species" => result.species.clone(),
+                                        "type" => result._type.clone(),
+                                        "gender" => result.gender.clone(),
+                                        _ => unreachable!("field names are checked above"),
                                     };
                                     field_value.contains(&field_value_check)
                                 })
-                                .map(|result| result.clone())
+                                .cloned()
                                 .collect::<Vec<_>>();
                             new_page.results = new_page_result;
                         }
@@ -126,6 +157,16 @@ impl OperationListEvaluator for RMResponse {
                         OperationEnum::Sort(sort_direction, field_name) => {
                             let field_name: String = field_name.into();
                             let sort_direction: String = sort_direction.into();
+                            CHARACTER_FIELDS.check_sortable(&field_name)?;
+                            let created = |character: &Character| {
+                                DateTime::parse_from_rfc3339(&character.created).ok()
+                            };
+                            if field_name == "created" {
+                                check_created_dates(
+                                    "character",
+                                    new_page.results.iter().map(|character| (character.id, character.created.as_str())),
+                                )?;
+                            }
                             match sort_direction.as_str() {
                                 "ASC" => {
                                     new_page.results.sort_by(|a, b| match field_name.as_str() {
@@ -133,7 +174,9 @@ impl OperationListEvaluator for RMResponse {
                                         "status" => a.status.cmp(&b.status),
                                         "species" => a.species.cmp(&b.species),
                                         "type" => a._type.cmp(&b._type),
-                                        _ => a.name.cmp(&b.name),
+                                        "id" => a.id.cmp(&b.id),
+                                        "created" => created(a).cmp(&created(b)),
+                                        _ => unreachable!("field names are checked above"),
                                     })
                                 }
                                 "DSC" => {
@@ -142,7 +185,9 @@ impl OperationListEvaluator for RMResponse {
                                         "status" => b.status.cmp(&a.status),
                                         "species" => b.species.cmp(&a.species),
                                         "type" => b._type.cmp(&a._type),
-                                        _ => b.name.cmp(&a.name),
+                                        "id" => b.id.cmp(&a.id),
+                                        "created" => created(b).cmp(&created(a)),
+                                        _ => unreachable!("field names are checked above"),
                                     });
                                 }
                                 _ => {},
@@ -164,19 +209,21 @@ impl OperationListEvaluator for RMResponse {
                         OperationEnum::Contains(field_name, field_value) => {
                             let field_name: String = field_name.into();
                             let field_value_check: String = field_value.into();
+                            EPISODE_FIELDS.check_searchable(&field_name)?;
                             let new_page_result = new_page
                                 .results
                                 .iter()
                                 .filter(|&result| {
                                     let field_value = match field_name.as_str() {
-                                        "name" => &result.name,
-                                        "air_date" => &result.air_date,
-                                        "episode" => &result.episode,
-                                        _ => "",
+                                        "id" => result.id.to_string(),
+                                        "name" => result.name.clone(),
+                                        "air_date" => result.air_date.clone(),
+                                        "episode" => result.episode.clone(),
+                                        _ => unreachable!("field names are checked above"),
                                     };
                                     field_value.contains(&field_value_check)
                                 })
-                                .map(|result| result.clone())
+                                .cloned()
                                 .collect::<Vec<_>>();
                             new_page.results = new_page_result;
                         }
@@ -185,25 +232,48 @@ impl OperationListEvaluator for RMResponse {
                         OperationEnum::Sort(sort_direction, field_name) => {
                             let field_name: String = field_name.into();
                             let sort_direction: String = sort_direction.into();
+                            EPISODE_FIELDS.check_sortable(&field_name)?;
+                            let created = |episode: &Episode| {
+                                DateTime::parse_from_rfc3339(&episode.created).ok()
+                            };
+                            let air_date = |episode: &Episode| {
+                                NaiveDate::parse_from_str(&episode.air_date, AIR_DATE_FORMAT).ok()
+                            };
+                            if field_name == "created" {
+                                check_created_dates(
+                                    "episode",
+                                    new_page.results.iter().map(|episode| (episode.id, episode.created.as_str())),
+                                )?;
+                            }
+                            if field_name == "air_date" {
+                                if let Some(episode) = new_page.results.iter().find(|episode| air_date(episode).is_none()) {
+                                    return Err(RickError::Parse(format!(
+                                        "episode {} has an invalid air date `{}`",
+                                        episode.id, episode.air_date
+                                    )));
+                                }
+                            }
                             match sort_direction.as_str() {
                                 "ASC" => {
                                     new_page.results.sort_by(|a, b| match field_name.as_str() {
                                         "name" => a.name.cmp(&b.name),
                                         "id" => a.id.cmp(&b.id),
-                                        "air_date" => std::cmp::Ordering::Equal,
+                                        "air_date" => air_date(a).cmp(&air_date(b)),
                                         "episode" => a.episode.cmp(&b.episode),
                                         "characters" => a.characters.cmp(&b.characters),
-                                        _ => std::cmp::Ordering::Equal,
+                                        "created" => created(a).cmp(&created(b)),
+                                        _ => unreachable!("field names are checked above"),
                                     })
                                 }
                                 "DSC" => {
                                     new_page.results.sort_by(|a, b| match field_name.as_str() {
                                         "name" => b.name.cmp(&a.name),
                                         "id" => b.id.cmp(&a.id),
-                                        "air_date" => std::cmp::Ordering::Equal,
+                                        "air_date" => air_date(b).cmp(&air_date(a)),
                                         "episode" => b.episode.cmp(&a.episode),
                                         "characters" => b.characters.cmp(&a.characters),
-                                        _ => std::cmp::Ordering::Equal,
+                                        "created" => created(b).cmp(&created(a)),
+                                        _ => unreachable!("field names are checked above"),
                                     });
                                 }
                                 _ => {},
@@ -224,11 +294,25 @@ impl OperationListEvaluator for RMResponse {
     }
 }
 
+/// Fails on the first result whose `created` date is not RFC 3339, so a SORT
+/// by `created` never compares a date that is missing.
+fn check_created_dates<'a>(
+    entity: &str,
+    mut dates: impl Iterator<Item = (u32, &'a str)>,
+) -> Result<(), RickError> {
+    match dates.find(|(_, created)| DateTime::parse_from_rfc3339(created).is_err()) {
+        Some((id, created)) => Err(RickError::Parse(format!(
+            "{} {} has an invalid created date `{}`",
+            entity, id, created
+        ))),
+        None => Ok(()),
+    }
+}
+
 // tests
 #[cfg(test)]
+#[allow(clippy::single_match)]
 mod tests {
-    use tokio;
-
     use crate::lib::{
         query_language::operand::{Operand, OperandEnum},
         rm_api::entities::*,
@@ -337,8 +421,8 @@ mod tests {
     #[tokio::test]
     async fn sort_operation_characters() {
         let operation_list = OperationList(vec![Operation(OperationEnum::Sort(
-            Operand(OperandEnum::String("name".into())),
             Operand(OperandEnum::String("ASC".into())),
+            Operand(OperandEnum::String("name".into())),
         ))]);
 
         let response = RMResponse(RMResponseEnum::Characters(CharacterPage {
@@ -371,8 +455,8 @@ mod tests {
         }
 
         let operation_list = OperationList(vec![Operation(OperationEnum::Sort(
-            Operand(OperandEnum::String("name".into())),
             Operand(OperandEnum::String("DSC".into())),
+            Operand(OperandEnum::String("name".into())),
         ))]);
 
         let evaluated_response = response.evaluate_op(&operation_list).await.unwrap();
@@ -502,4 +586,95 @@ mod tests {
             _ => {}
         }
     }
+
+    #[tokio::test]
+    async fn invalid_created_dates_are_an_error() {
+        let operation_list = OperationList(vec![Operation(OperationEnum::Sort(
+            Operand(OperandEnum::String("ASC".into())),
+            Operand(OperandEnum::String("created".into())),
+        ))]);
+        let response = RMResponse(RMResponseEnum::Locations(LocationPage {
+            results: vec![
+                Location {
+                    id: 1,
+                    created: "2017-11-10T12:42:04.162Z".into(),
+                    ..Default::default()
+                },
+                Location {
+                    id: 2,
+                    created: "".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }));
+
+        assert_eq!(
+            response.evaluate_op(&operation_list).await.err(),
+            Some(RickError::Parse(
+                "location 2 has an invalid created date ``".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn sort_operation_episodes_by_air_date() {
+        let episode = |id, air_date: &str| Episode {
+            id,
+            air_date: air_date.into(),
+            ..Default::default()
+        };
+        let response = RMResponse(RMResponseEnum::Episodes(EpisodePage {
+            results: vec![
+                episode(1, "April 7, 2014"),
+                episode(2, "December 2, 2013"),
+                episode(3, "December 16, 2013"),
+            ],
+            ..Default::default()
+        }));
+        for (direction, expected) in [("ASC", vec![2, 3, 1]), ("DSC", vec![1, 3, 2])] {
+            let operation_list = OperationList(vec![Operation(OperationEnum::Sort(
+                Operand(OperandEnum::String(direction.into())),
+                Operand(OperandEnum::String("air_date".into())),
+            ))]);
+            match response.evaluate_op(&operation_list).await.unwrap().0 {
+                RMResponseEnum::Episodes(page) => {
+                    let ids: Vec<u32> = page.results.iter().map(|episode| episode.id).collect();
+                    assert_eq!(ids, expected);
+                }
+                _ => panic!(),
+            }
+        }
+
+        let operation_list = OperationList(vec![Operation(OperationEnum::Sort(
+            Operand(OperandEnum::String("ASC".into())),
+            Operand(OperandEnum::String("air_date".into())),
+        ))]);
+        let response = RMResponse(RMResponseEnum::Episodes(EpisodePage {
+            results: vec![episode(4, "soon")],
+            ..Default::default()
+        }));
+        assert_eq!(
+            response.evaluate_op(&operation_list).await.err(),
+            Some(RickError::Parse("episode 4 has an invalid air date `soon`".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_field_is_an_error() {
+        let operation_list = OperationList(vec![Operation(OperationEnum::Contains(
+            Operand(OperandEnum::String("origin".into())),
+            Operand(OperandEnum::String("Earth".into())),
+        ))]);
+        let response = RMResponse(RMResponseEnum::Characters(CharacterPage::default()));
+
+        let evaluated_response = response.evaluate_op(&operation_list).await;
+        assert_eq!(
+            evaluated_response.err(),
+            Some(RickError::UnknownField {
+                entity: "characters".into(),
+                field: "origin".into(),
+            })
+        );
+    }
 }
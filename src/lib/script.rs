@@ -0,0 +1,265 @@
+use std::path::PathBuf;
+
+use super::{
+    error::RickError,
+    explain::explain,
+    export::{export_to_file, ExportFormat},
+    planner::{run_pipeline, Bindings, Plan, Statement},
+    query_language::{macros::Macros, operation_list::OperationListEvaluator, split_top_level},
+    rm_api::response::RMResponseEnum,
+};
+
+/// What a line of a `.rick` script asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Statement(Statement),
+    /// Write the results of the last query to a file
+    Export(ExportFormat, PathBuf),
+    /// Tell what a query would request and do, without running it
+    Explain(Plan),
+}
+
+/// The statements of a `.rick` script, each with the line it starts on.
+///
+/// Statements end at the end of a line or at a `;` outside parentheses and
+/// quotes. A line starting with
+/// `::` or `|` carries on the statement above it, and lines starting with
+/// `#` are comments:
+///
+/// ```text
+/// # the residents of the citadel, by name
+/// LET citadel = LOCATIONS::NAME(Citadel)
+/// CHARACTERS::IDS($citadel.residents)
+///     ::SORT(ASC, name)
+/// EXPORT csv citadel.csv
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub statements: Vec<(usize, String)>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Self {
+        let mut statements: Vec<(usize, String)> = vec![];
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let continues = line.starts_with("::") || line.starts_with('|');
+            for (part_index, part) in split_top_level(line, ";").into_iter().enumerate() {
+                match statements.last_mut() {
+                    Some((_, statement)) if continues && part_index == 0 => {
+                        if part.starts_with('|') {
+                            statement.push(' ');
+                        }
+                        statement.push_str(part)
+                    }
+                    _ if part.trim().is_empty() => {}
+                    _ => statements.push((index + 1, part.trim().to_string())),
+                }
+            }
+        }
+        Script { statements }
+    }
+}
+
+impl Directive {
+    /// Reads `EXPORT csv|json path`, `EXPLAIN query`, or a statement, with
+    /// `macros` expanded in the queries. The path is the rest of the
+    /// statement, and may be in double quotes.
+    pub fn parse(statement: &str, macros: &Macros) -> Result<Self, RickError> {
+        if let Some(query) = statement.strip_prefix("EXPLAIN ") {
+            return Ok(Directive::Explain(Plan::parse_with(query, macros)?));
+        }
+        let Some(arguments) = statement.strip_prefix("EXPORT ") else {
+            return Ok(Directive::Statement(Statement::parse(statement, macros)?));
+        };
+        let (format, path) = arguments
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or_default();
+        let path = path.trim();
+        let path = match path.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"').unwrap_or(quoted),
+            None => path,
+        };
+        if path.is_empty() {
+            return Err(RickError::Parse(
+                "expected `EXPORT csv|json path`".to_string(),
+            ));
+        }
+        Ok(Directive::Export(format.parse()?, PathBuf::from(path)))
+    }
+}
+
+/// Runs every statement of a script in order, calling `report` with the
+/// line and the outcome of each. A failing statement does not stop the
+/// ones after it. `EXPLAIN` builds its URLs for `base_url`. Returns how
+/// many failed.
+pub async fn run_script<T, F>(
+    request: T,
+    base_url: &str,
+    script: &Script,
+    macros: &Macros,
+    mut report: F,
+) -> usize
+where
+    T: OperationListEvaluator + Clone + Send + Sync,
+    F: FnMut(usize, Result<String, RickError>),
+{
+    let mut bindings = Bindings::new();
+    let mut last: Option<RMResponseEnum> = None;
+    let mut failed = 0;
+    for (line, statement) in &script.statements {
+        let outcome = run_statement(
+            &request,
+            base_url,
+            statement,
+            macros,
+            &mut bindings,
+            &mut last,
+        )
+        .await;
+        if outcome.is_err() {
+            failed += 1;
+        }
+        report(*line, outcome);
+    }
+    failed
+}
+
+async fn run_statement<T>(
+    request: &T,
+    base_url: &str,
+    statement: &str,
+    macros: &Macros,
+    bindings: &mut Bindings,
+    last: &mut Option<RMResponseEnum>,
+) -> Result<String, RickError>
+where
+    T: OperationListEvaluator + Clone + Send + Sync,
+{
+    match Directive::parse(statement, macros)? {
+        Directive::Statement(Statement::Let(name, stages)) => {
+            let (response, _) = run_pipeline(request, &stages, bindings, &|_| {}).await?;
+            let message = format!("${} = {} results", name, response.result_count());
+            bindings.insert(name, response);
+            Ok(message)
+        }
+        Directive::Statement(Statement::Query(stages)) => {
+            let (response, stats) = run_pipeline(request, &stages, bindings, &|_| {}).await?;
+            let message = format!(
+                "{} results in {} ms",
+                stats.shown,
+                stats.elapsed.as_millis()
+            );
+            *last = Some(response);
+            Ok(message)
+        }
+        Directive::Explain(plan) => {
+            // bindings made earlier in the script are explained as run time values
+            Ok(explain(base_url, &plan)?.to_string())
+        }
+        Directive::Export(format, path) => {
+            let response = last.as_ref().ok_or_else(|| {
+                RickError::Parse("nothing to export, EXPORT follows a query".to_string())
+            })?;
+            let rows = export_to_file(response, format, &path)?;
+            Ok(format!("exported {} rows to {}", rows, path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::{request::mock_request::MockRequest, DEFAULT_BASE_URL};
+
+    #[test]
+    fn reads_statements_by_line() {
+        let parse = |statement| Directive::parse(statement, &Macros::in_memory());
+        let script = Script::parse(
+            "# the pilot\nLET pilot = EPISODES::NAME(Pilot)\n\nCHARACTERS::IDS($pilot.characters)\n  ::LIMIT(2)\n  | EPISODES; EXPORT json out.json\n",
+        );
+        assert_eq!(
+            script.statements,
+            vec![
+                (2, "LET pilot = EPISODES::NAME(Pilot)".to_string()),
+                (
+                    4,
+                    "CHARACTERS::IDS($pilot.characters)::LIMIT(2) | EPISODES".to_string()
+                ),
+                (6, "EXPORT json out.json".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("EXPORT csv out.csv").unwrap(),
+            Directive::Export(ExportFormat::Csv, "out.csv".into())
+        );
+        assert_eq!(
+            parse("EXPORT json my results.json").unwrap(),
+            Directive::Export(ExportFormat::Json, "my results.json".into())
+        );
+        assert_eq!(
+            parse("EXPORT csv \"a; b.csv\"").unwrap(),
+            Directive::Export(ExportFormat::Csv, "a; b.csv".into())
+        );
+        assert_eq!(
+            Script::parse("EPISODES; EXPORT csv \"a; b.csv\"").statements,
+            vec![
+                (1, "EPISODES".to_string()),
+                (1, "EXPORT csv \"a; b.csv\"".to_string())
+            ]
+        );
+        assert!(parse("EXPORT out.csv").is_err());
+        assert_eq!(
+            parse("EXPLAIN EPISODES").unwrap(),
+            Directive::Explain(Plan::parse("EPISODES").unwrap())
+        );
+
+        let mut macros = Macros::in_memory();
+        macros.save("pilot", "EPISODES::NAME(Pilot)").unwrap();
+        assert_eq!(
+            Directive::parse("EXPLAIN @pilot", &macros).unwrap(),
+            Directive::Explain(Plan::parse("EPISODES::NAME(Pilot)").unwrap())
+        );
+        assert_eq!(
+            Directive::parse("LET p = @pilot::LIMIT(1)", &macros).unwrap(),
+            Directive::Statement(Statement::Let(
+                "p".into(),
+                vec!["EPISODES::NAME(Pilot)::LIMIT(1)".into()]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_scripts_and_reports_errors_by_line() {
+        let dir = std::env::temp_dir().join(format!("rick_cli_script_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pilot.csv");
+        let source = format!(
+            "EXPORT csv {path}\nLET pilot = EPISODES::NAME(Pilot)\nCHARACTERS::NAEM(rick)\nCHARACTERS::IDS($pilot.characters)::LIMIT(2)\nEXPORT csv {path}\n",
+            path = path.display()
+        );
+
+        let mut reports = vec![];
+        let failed = run_script(
+            MockRequest,
+            DEFAULT_BASE_URL,
+            &Script::parse(&source),
+            &Macros::in_memory(),
+            |line, outcome| reports.push((line, outcome.is_ok())),
+        )
+        .await;
+        assert_eq!(failed, 2);
+        assert_eq!(
+            reports,
+            vec![(1, false), (2, true), (3, false), (4, true), (5, true)]
+        );
+        let exported = std::fs::read_to_string(&path).unwrap();
+        assert!(exported.contains("Rick Sanchez"));
+        assert_eq!(exported.lines().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,91 +1,332 @@
+#![allow(special_module_name)]
+
+mod command_line;
+mod config;
+mod engine;
+mod keymap;
 mod lib;
+mod portrait;
 mod renderer;
+mod repl;
+mod table;
+mod theme;
 // use futures::executor::block_on;
+use config::{Config, Layer, Mode};
+use engine::{Backend, Engine};
+use portrait::Protocol;
 use renderer::Renderer;
-use std::{
-    collections::HashMap,
-    error::Error,
-    sync::{mpsc, Arc, Mutex},
-    thread,
-};
+use repl::Repl;
+use table::Columns;
+use theme::Theme;
+use std::{error::Error, path::PathBuf, sync::Arc, thread, time::Duration};
+use tokio::sync::mpsc;
 // use lib::rm_api::Rickuest;
 // use lib::query_language::*;
-use clap::Parser;
-use lib::rm_api::{request::direct_request::DirectRequest, response::RMResponseEnum};
-
-use crate::lib::query_api;
+use clap::{Parser, Subcommand};
+use lib::history::History;
+use lib::query_language::macros::Macros;
+use lib::rm_api::{
+    local_store::LocalStore,
+    request::{
+        direct_request::DirectRequest,
+        local_request::LocalRequest,
+        mock_request::MockRequest,
+        http_transport::{HttpConfig, HttpTransport},
+        transport::{CachingTransport, RecordingTransport, ReplayTransport, Transport},
+    },
+};
+use lib::script::{run_script, Script};
 
-// #[derive(Parser)]
+#[derive(Parser)]
+#[command(about = "Query the Rick and Morty API")]
 struct Args {
-    mode: Mode,
+    /// Where queries are answered from, defaults to direct
+    #[arg(long, value_enum)]
+    mode: Option<Mode>,
+    /// The server direct mode and `sync` talk to
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Directory holding the local mirror, defaults to the user data directory
+    #[arg(long)]
+    mirror_dir: Option<PathBuf>,
+    /// Seconds to wait for an API response before retrying, defaults to 10
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// How many times a failed API request is retried, defaults to 3
+    #[arg(long)]
+    retries: Option<u32>,
+    /// Send API requests through this proxy
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Record every API request and response to a cassette file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer API requests from a cassette file instead of the network
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Seconds API responses are reused for, 0 turns the cache off; defaults to 300
+    #[arg(long)]
+    cache_ttl: Option<u64>,
+    /// Added as `LIMIT(n)` to queries that have no LIMIT, to the last query of a plan
+    #[arg(long)]
+    default_limit: Option<u32>,
+    /// Columns shown for a root, e.g `LOCATIONS=name,dimension,residents_count`; repeat for each root
+    #[arg(long = "columns", value_name = "ROOT=FIELDS")]
+    columns: Vec<String>,
+    /// How character portraits are drawn, detected from the terminal by default
+    #[arg(long, value_enum)]
+    portraits: Option<Protocol>,
+    /// A theme (dark, light, high-contrast, no-colour) or a theme file, defaults to `theme.toml` in the config directory
+    #[arg(long, value_name = "NAME|FILE")]
+    theme: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-enum Mode {
-    Proxy,
-    Direct,
+#[derive(Subcommand)]
+enum Command {
+    /// Download every character, location and episode into the local mirror
+    Sync,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Manage the saved queries that run as `@name(args)`
+    Macro {
+        #[command(subcommand)]
+        command: MacroCommand,
+    },
+    /// Run the statements of a `.rick` script, reporting each by line
+    Run { script: PathBuf },
+    /// Read queries line by line and print the results, instead of the full screen UI
+    Repl,
 }
 
-#[derive(Debug,Clone)]
-struct ResultState {
-    value: Option<RMResponseEnum>,
-    id: String,
-    error_msg: Option<String>,
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration, merged from every layer
+    Show,
 }
 
-#[derive(Default)]
-pub struct AppState {
-    results: HashMap<String, ResultState>,
+#[derive(Subcommand)]
+enum MacroCommand {
+    /// List the saved macros
+    List,
+    /// Save a query under a name, e.g `save 'top_by_name($n)' 'NAME($n)::LIMIT(10)'`
+    Save { signature: String, query: String },
+    /// Forget a macro
+    Remove { name: String },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let app_state = Arc::new(Mutex::new(AppState::default()));
-    let render_app_state = app_state.clone();
-    let (tx, rx) = mpsc::channel::<String>();
+    let args = Args::parse();
+    let data_dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rick_cli");
+    let mirror_dir = args.mirror_dir.unwrap_or_else(|| data_dir.clone());
+
+    // flags override the environment, which overrides the config files
+    let mut flags = Layer::new("command line");
+    flags.set("mode", args.mode)?;
+    flags.set("base_url", args.base_url)?;
+    flags.set("timeout", args.timeout)?;
+    flags.set("retries", args.retries)?;
+    flags.set("cache_ttl", args.cache_ttl)?;
+    flags.set("default_limit", args.default_limit)?;
+    flags.set("theme", args.theme)?;
+    let mut layers = Config::layers()?;
+    layers.push(flags);
+    let config = Config::merge(&layers)?;
+    if let Some(Command::Config {
+        command: ConfigCommand::Show,
+    }) = args.command
+    {
+        print!("{}", config.show(&layers)?);
+        return Ok(());
+    }
+    if let Some(Command::Macro { command }) = args.command {
+        return manage_macros(command, &data_dir);
+    }
+
+    let http_transport = Arc::new(HttpTransport::new(HttpConfig {
+        timeout: Duration::from_secs(config.timeout),
+        max_retries: config.retries,
+        proxy: args.proxy,
+        ..Default::default()
+    })?);
+    let mut backend_label = String::new();
+    let transport: Arc<dyn Transport> = match (args.record, args.replay) {
+        (Some(path), _) => {
+            backend_label = format!(" (recording to {})", path.display());
+            Arc::new(RecordingTransport::new(http_transport, &path)?)
+        }
+        (_, Some(path)) => {
+            backend_label = format!(" (replaying {})", path.display());
+            Arc::new(ReplayTransport::load(&path)?)
+        }
+        _ => http_transport,
+    };
+
+    if let Some(Command::Sync) = args.command {
+        let direct_request = DirectRequest::with_transport(transport).with_base_url(&config.base_url);
+        return sync(direct_request, &config.base_url, &mirror_dir).await;
+    }
+
+    let transport: Arc<dyn Transport> = match config.cache_ttl {
+        0 => transport,
+        ttl => Arc::new(CachingTransport::new(transport, Duration::from_secs(ttl))),
+    };
+    let direct = Backend::Direct(
+        DirectRequest::with_transport(transport.clone()).with_base_url(&config.base_url),
+    );
+    // a missing mirror only matters once local mode is asked for
+    let local = LocalStore::load(&mirror_dir)
+        .map(|store| Backend::Local(LocalRequest(Arc::new(store))));
+    let backend = match config.mode {
+        Mode::Direct => direct.clone(),
+        Mode::Local => local.clone()?,
+        Mode::Mock => Backend::Mock,
+        Mode::Proxy => return Err("proxy mode is not implemented yet".into()),
+    };
+
+    if let Some(Command::Run { script }) = &args.command {
+        return run(script, backend, &data_dir).await;
+    }
+
+    let backend_label = format!("{}{}", backend.name(), backend_label);
+
+    let (commands_tx, commands_rx) = mpsc::unbounded_channel::<engine::Command>();
+    let (events_tx, events_rx) = std::sync::mpsc::channel::<engine::Event>();
+
+    let mut columns = Columns::default();
+    for spec in &args.columns {
+        columns.set(spec)?;
+    }
 
+    // a broken history should not keep the app from starting
+    let history = History::load(&data_dir).unwrap_or_else(|err| {
+        eprintln!("query history is disabled: {}", err);
+        History::in_memory()
+    });
+
+    let macros = Macros::load(&data_dir).unwrap_or_else(|err| {
+        eprintln!("macros are disabled: {}", err);
+        Macros::in_memory()
+    });
+
+    let portraits = args.portraits.unwrap_or_else(Protocol::detect);
+
+    let theme_file = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rick_cli")
+        .join("theme.toml");
+    let mut theme = match (&config.theme, theme_file.exists()) {
+        (Some(spec), _) => Theme::load(spec)?,
+        (None, true) => Theme::load(&theme_file.to_string_lossy())?,
+        (None, false) => Theme::default(),
+    };
+    // https://no-color.org
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        theme = theme.without_colours();
+    }
+
+    let repl = matches!(args.command, Some(Command::Repl));
     let render_thread = thread::spawn(move || {
-        let renderer = Renderer::new(tx, render_app_state);
-        renderer.start();
+        let result = if repl {
+            Repl::new(commands_tx, events_rx, history, columns, backend_label).start()
+        } else {
+            Renderer::new(commands_tx, events_rx, history, columns, backend_label)
+                .with_portraits(portraits)
+                .with_theme(theme)
+                .with_keymap(config.keys)
+                .start()
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
     });
 
-    for request in rx {
-        apply_request_state(request, app_state.clone()).await;
-        // use tokio spawn for the above
-        // let app_state = app_state.clone();
-        // tokio::spawn(async move {
-        //     let result = apply_request_state(request, app_state).await;
-          
-        // });
+    let mut engine = Engine::new(backend, events_tx)
+        .with_images(transport)
+        .with_default_limit(config.default_limit)
+        .with_macros(macros)
+        .with_backend(Mode::Direct, direct);
+    if let Ok(local) = local {
+        engine = engine.with_backend(Mode::Local, local);
     }
+    engine.run(commands_rx).await;
     render_thread.join().unwrap();
     Ok(())
 }
 
-async fn apply_request_state(request: String, app_state: Arc<Mutex<AppState>>) {
-    let request_str = request.as_str();
-    let mut state = app_state.lock().unwrap();
-    state.results.clear();
-    state.results.insert(
-        request_str.to_string(),
-        ResultState {
-            id: request_str.to_string(),
-            value: None,
-            error_msg: None,
-        },
-    );
-    // let mock_query_result = query_api(MockRequest, request_str).await;
-    let query_result = query_api(DirectRequest, request_str).await;
-    let new_result_state = match query_result {
-        Ok(response) => ResultState {
-            error_msg: None,
-            id: request_str.to_string(),
-            value: Some(response),
-        },
-        Err(err) => ResultState {
-            error_msg: Some(err.to_string()),
-            id: request_str.to_string(),
-            value: None,
-        },
+fn manage_macros(command: MacroCommand, data_dir: &std::path::Path) -> Result<(), Box<dyn Error>> {
+    let mut macros = Macros::load(data_dir)?;
+    match command {
+        MacroCommand::List => {
+            for (name, saved) in macros.iter() {
+                println!("{} = {}", saved.signature(name), saved.query);
+            }
+        }
+        MacroCommand::Save { signature, query } => {
+            println!("Saved {}", macros.save(&signature, &query)?);
+        }
+        MacroCommand::Remove { name } => {
+            if !macros.remove(&name)? {
+                return Err(format!("there is no macro named {}", name).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run(
+    path: &std::path::Path,
+    backend: Backend,
+    data_dir: &std::path::Path,
+) -> Result<(), Box<dyn Error>> {
+    let script = Script::parse(&std::fs::read_to_string(path)?);
+    let macros = Macros::load(data_dir)?;
+    let report = |line: usize, outcome: Result<String, lib::error::RickError>| match outcome {
+        Ok(message) => println!("{}:{}: {}", path.display(), line, message),
+        Err(err) => eprintln!("{}:{}: {}: {}", path.display(), line, err.kind(), err),
     };
-    state.results.insert(request, new_result_state);
+    let base_url = backend.base_url().to_string();
+    let failed = match backend {
+        Backend::Direct(direct_request) => {
+            run_script(direct_request, &base_url, &script, &macros, report).await
+        }
+        Backend::Local(local_request) => {
+            run_script(local_request, &base_url, &script, &macros, report).await
+        }
+        Backend::Mock => run_script(MockRequest, &base_url, &script, &macros, report).await,
+    };
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} statements failed",
+            failed,
+            script.statements.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn sync(
+    direct_request: DirectRequest,
+    base_url: &str,
+    mirror_dir: &std::path::Path,
+) -> Result<(), Box<dyn Error>> {
+    println!("Downloading characters, locations and episodes...");
+    let store = LocalStore::sync(direct_request, base_url).await?;
+    store.save(mirror_dir)?;
+    println!(
+        "Synced {} characters, {} locations and {} episodes to {}",
+        store.characters.len(),
+        store.locations.len(),
+        store.episodes.len(),
+        mirror_dir.display()
+    );
+    Ok(())
 }
@@ -0,0 +1,304 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use clap::ValueEnum;
+use image::{
+    codecs::png::PngEncoder, imageops::FilterType, ColorType, ImageEncoder, Rgba, RgbaImage,
+};
+use tui::{buffer::Buffer, layout::Rect, style::Color, widgets::Widget};
+
+use crate::lib::error::RickError;
+
+/// Larger images are scaled down when decoded, the API's avatars are 300x300
+const MAX_SIDE: u32 = 300;
+/// The size of a terminal cell in pixels assumed for sixel, which draws in pixels
+const CELL_WIDTH: u32 = 10;
+const CELL_HEIGHT: u32 = 20;
+/// Kitty takes the image in pieces of at most this many base64 bytes
+const KITTY_CHUNK: usize = 4096;
+
+/// How portraits are drawn in the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Protocol {
+    /// The kitty graphics protocol, also spoken by Ghostty
+    Kitty,
+    /// iTerm2's inline images, also spoken by WezTerm
+    Iterm,
+    Sixel,
+    /// Unicode half blocks coloured with two pixels each, works everywhere
+    Blocks,
+    /// Portraits are not downloaded at all
+    #[default]
+    Off,
+}
+
+impl Protocol {
+    /// Picks the best protocol the terminal announces through its environment.
+    pub fn detect() -> Self {
+        Self::detect_from(|name| std::env::var(name).ok())
+    }
+
+    fn detect_from(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        if var("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "ghostty" {
+            Protocol::Kitty
+        } else if program == "iTerm.app"
+            || program == "WezTerm"
+            || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+        {
+            Protocol::Iterm
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Protocol::Sixel
+        } else {
+            Protocol::Blocks
+        }
+    }
+
+    /// The escape sequence drawing `image` into `columns` x `rows` cells at
+    /// the cursor, `None` for protocols drawn with cells instead.
+    pub fn escape_sequence(self, image: &RgbaImage, columns: u16, rows: u16) -> Option<String> {
+        match self {
+            Protocol::Kitty => Some(kitty(image, columns, rows)),
+            Protocol::Iterm => Some(iterm(image, columns, rows)),
+            Protocol::Sixel => Some(sixel(&fit(
+                image,
+                columns as u32 * CELL_WIDTH,
+                rows as u32 * CELL_HEIGHT,
+            ))),
+            Protocol::Blocks | Protocol::Off => None,
+        }
+    }
+
+    /// Removes images this protocol left on the screen, which redrawing the
+    /// cells does not do for kitty.
+    pub fn clear_sequence(self) -> &'static str {
+        match self {
+            Protocol::Kitty => "\x1b_Ga=d,q=2\x1b\\",
+            _ => "",
+        }
+    }
+}
+
+/// Decodes a downloaded PNG or JPEG.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage, RickError> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|err| RickError::Decode(format!("could not read the portrait ({})", err)))?;
+    if image.width() > MAX_SIDE || image.height() > MAX_SIDE {
+        return Ok(image.thumbnail(MAX_SIDE, MAX_SIDE).to_rgba8());
+    }
+    Ok(image.to_rgba8())
+}
+
+/// Scales `image` to fit into `width` x `height` pixels, keeping its aspect ratio.
+fn fit(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let scale = f64::min(
+        width as f64 / image.width() as f64,
+        height as f64 / image.height() as f64,
+    );
+    let width = ((image.width() as f64 * scale) as u32).max(1);
+    let height = ((image.height() as f64 * scale) as u32).max(1);
+    if (width, height) == image.dimensions() {
+        return image.clone();
+    }
+    image::imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+/// Draws an image with `▀`, the top pixel as the foreground colour and the
+/// bottom one as the background, so every cell shows two pixels.
+pub struct HalfBlocks<'a>(pub &'a RgbaImage);
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let image = fit(self.0, area.width as u32, area.height as u32 * 2);
+        let left = area.x + (area.width - image.width() as u16) / 2;
+        for y in 0..(image.height() as u16).div_ceil(2) {
+            for x in 0..image.width() as u16 {
+                let top = image.get_pixel(x as u32, y as u32 * 2);
+                let bottom = image
+                    .get_pixel_checked(x as u32, y as u32 * 2 + 1)
+                    .map(colour)
+                    .unwrap_or(Color::Reset);
+                buf.get_mut(left + x, area.y + y)
+                    .set_symbol("▀")
+                    .set_fg(colour(top))
+                    .set_bg(bottom);
+            }
+        }
+    }
+}
+
+fn colour(pixel: &Rgba<u8>) -> Color {
+    match pixel.0 {
+        [_, _, _, 0] => Color::Reset,
+        [r, g, b, _] => Color::Rgb(r, g, b),
+    }
+}
+
+fn png(image: &RgbaImage) -> Vec<u8> {
+    let mut bytes = vec![];
+    PngEncoder::new(&mut bytes)
+        .write_image(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            ColorType::Rgba8,
+        )
+        .expect("encoding to memory should not fail");
+    bytes
+}
+
+fn kitty(image: &RgbaImage, columns: u16, rows: u16) -> String {
+    let data = STANDARD.encode(png(image));
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut sequence = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        // base64 is ASCII, so every chunk is valid UTF-8
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if index == 0 {
+            // q=2 keeps the terminal from answering on stdin, C=1 leaves the cursor alone
+            let _ = write!(
+                sequence,
+                "\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};{}\x1b\\",
+                columns, rows, more, chunk
+            );
+        } else {
+            let _ = write!(sequence, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    sequence
+}
+
+fn iterm(image: &RgbaImage, columns: u16, rows: u16) -> String {
+    let png = png(image);
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        png.len(),
+        columns,
+        rows,
+        STANDARD.encode(&png)
+    )
+}
+
+/// Encodes an image as sixels, with colours rounded to a 6x6x6 cube.
+fn sixel(image: &RgbaImage) -> String {
+    let level = |channel: u8| (channel as usize * 5 + 127) / 255;
+    let mut sequence = format!("\x1bPq\"1;1;{};{}", image.width(), image.height());
+    for index in 0..216 {
+        // sixel colours are percentages
+        let (r, g, b) = (index / 36 * 20, index / 6 % 6 * 20, index % 6 * 20);
+        let _ = write!(sequence, "#{};2;{};{};{}", index, r, g, b);
+    }
+    for band in (0..image.height()).step_by(6) {
+        // the six pixel columns of every colour used in this band
+        let mut colours: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for x in 0..image.width() {
+            for dy in 0..6.min(image.height() - band) {
+                let [r, g, b, a] = image.get_pixel(x, band + dy).0;
+                if a < 128 {
+                    continue;
+                }
+                let index = level(r) * 36 + level(g) * 6 + level(b);
+                colours
+                    .entry(index)
+                    .or_insert_with(|| vec![0; image.width() as usize])[x as usize] |= 1 << dy;
+            }
+        }
+        for (position, (index, bits)) in colours.iter().enumerate() {
+            if position > 0 {
+                // back to the start of the band to overlay the next colour
+                sequence.push('$');
+            }
+            let _ = write!(sequence, "#{}", index);
+            for run in bits.chunk_by(|a, b| a == b) {
+                let symbol = (63 + run[0]) as char;
+                if run.len() > 3 {
+                    let _ = write!(sequence, "!{}{}", run.len(), symbol);
+                } else {
+                    sequence.extend(std::iter::repeat_n(symbol, run.len()));
+                }
+            }
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::Rgb(255, 0, 0);
+    const BLUE: Color = Color::Rgb(0, 0, 255);
+
+    /// 4x4 pixels, the top half red and the bottom half blue
+    fn fixture() -> RgbaImage {
+        decode(include_bytes!("fixtures/portrait.png")).unwrap()
+    }
+
+    #[test]
+    fn detects_the_terminal() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            Protocol::detect_from(env(&[("KITTY_WINDOW_ID", "1")])),
+            Protocol::Kitty
+        );
+        assert_eq!(
+            Protocol::detect_from(env(&[("TERM_PROGRAM", "iTerm.app")])),
+            Protocol::Iterm
+        );
+        assert_eq!(
+            Protocol::detect_from(env(&[("TERM", "foot")])),
+            Protocol::Sixel
+        );
+        assert_eq!(
+            Protocol::detect_from(env(&[("TERM", "xterm-256color")])),
+            Protocol::Blocks
+        );
+    }
+
+    #[test]
+    fn draws_half_blocks() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 6, 2));
+        HalfBlocks(&fixture()).render(buf.area, &mut buf);
+        // centered in the six columns
+        assert_eq!(buf.get(0, 0).symbol, " ");
+        let top = buf.get(1, 0);
+        assert_eq!((top.symbol.as_str(), top.fg, top.bg), ("▀", RED, RED));
+        let bottom = buf.get(4, 1);
+        assert_eq!((bottom.fg, bottom.bg), (BLUE, BLUE));
+    }
+
+    #[test]
+    fn encodes_escape_sequences() {
+        let image = fixture();
+        assert_eq!(Protocol::Blocks.escape_sequence(&image, 4, 2), None);
+
+        let kitty = Protocol::Kitty.escape_sequence(&image, 4, 2).unwrap();
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,q=2,C=1,c=4,r=2,m=0;iVBORw0KGgo"));
+        let iterm = Protocol::Iterm.escape_sequence(&image, 4, 2).unwrap();
+        assert!(iterm.starts_with("\x1b]1337;File=inline=1;"));
+        assert!(iterm.ends_with('\x07'));
+
+        // two colours over four columns, each run drawn at once
+        let sixel = sixel(&image);
+        assert!(sixel.starts_with("\x1bPq\"1;1;4;4#0;2;0;0;0"));
+        assert!(sixel.ends_with("#5!4K$#180!4B-\x1b\\"));
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_image() {
+        assert!(matches!(decode(b"{}"), Err(RickError::Decode(_))));
+    }
+}
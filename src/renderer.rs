@@ -1,57 +1,495 @@
 use crossterm::{
-    event::{self, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent},
-    execute,
+    cursor::MoveTo,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event as TermEvent, KeyCode, KeyEvent,
+        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    execute, queue,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use image::RgbaImage;
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     error::Error,
-    io::{self},
-    sync::{mpsc::Sender, Arc, Mutex},
+    io::{self, Write},
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
 };
+use tokio::sync::mpsc::UnboundedSender;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Tabs,
+        Wrap,
+    },
     Frame, Terminal,
 };
 use tui_input::{
-    backend::{crossterm as backend, crossterm::EventHandler},
+    backend::crossterm::EventHandler,
     Input,
 };
 
 use crate::{
+    command_line::{self, LineCommand, COMMANDS},
+    engine::{Command, Event, TabId},
+    keymap::{Action, Keymap},
+    portrait::{HalfBlocks, Protocol},
+    theme::Theme,
+    table::{self, Columns},
     lib::{
-        query_language::operation_list::OperationList,
-        query_language::operation_list::OperationListEvaluator,
-        rm_api::{request::mock_request::MockRequest, response::RMResponseEnum},
+        error::RickError,
+        export::ExportFormat,
+        history::{History, HistoryEntry},
+        planner::Plan,
+        query_language::{
+            completion::complete,
+            highlight::{highlight, TokenKind},
+        },
+        rm_api::{entities::Entity, response::RMResponseEnum},
+        QueryStats,
     },
-    AppState, ResultState,
 };
 
+#[derive(Debug, Clone)]
+struct ResultState {
+    tab: TabId,
+    value: Option<RMResponseEnum>,
+    id: String,
+    error: Option<RickError>,
+    started_at: Instant,
+    /// The stage the query is in while it is loading
+    progress: Option<String>,
+    /// Index of the highlighted result
+    selected: usize,
+    /// Whether every field of the selected result is shown beside the list
+    detail: bool,
+    /// The column the results are ordered by, and whether it is descending
+    sort: Option<(usize, bool)>,
+    /// Only rows with a cell containing this are shown, set with `/`
+    filter: String,
+    stats: Option<QueryStats>,
+    /// What an `EXPLAIN` query would do, shown instead of a result
+    explanation: Option<String>,
+}
+
+/// A character's portrait, by how far downloading it got
+#[derive(Debug, Clone)]
+enum Portrait {
+    Loading,
+    Ready(RgbaImage),
+    Failed(String),
+}
+
+/// What the UI shows, built only from the events the engine sends.
+#[derive(Default)]
+pub struct AppState {
+    /// One tab per query, in the order they were opened
+    tabs: Vec<ResultState>,
+    /// Index into `tabs` of the tab being shown
+    active: usize,
+    /// A tab pinned to be shown beside the active one
+    compare: Option<TabId>,
+    next_tab: TabId,
+    /// Feedback for commands that do not produce a result, e.g exports
+    message: Option<String>,
+    history: History,
+    columns: Columns,
+    /// Where queries are answered from, e.g `Direct`
+    backend: String,
+    /// Downloaded portraits by URL
+    portraits: HashMap<String, Portrait>,
+    protocol: Protocol,
+    theme: Theme,
+}
+
+impl AppState {
+    pub fn new(history: History, columns: Columns, backend: String) -> Self {
+        AppState {
+            history,
+            columns,
+            backend,
+            ..Default::default()
+        }
+    }
+
+    /// Reserves the id for a tab that opens once its query starts.
+    fn new_tab_id(&mut self) -> TabId {
+        self.next_tab += 1;
+        self.next_tab
+    }
+
+    fn active_tab(&self) -> Option<&ResultState> {
+        self.tabs.get(self.active)
+    }
+
+    fn tab(&self, tab: TabId) -> Option<&ResultState> {
+        self.tabs.iter().find(|result_state| result_state.tab == tab)
+    }
+
+    fn tab_mut(&mut self, tab: TabId) -> Option<&mut ResultState> {
+        self.tabs.iter_mut().find(|result_state| result_state.tab == tab)
+    }
+
+    /// Moves to the next tab, or the previous one when `forward` is false, wrapping around.
+    fn switch_tab(&mut self, forward: bool) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        self.active = if forward {
+            (self.active + 1) % self.tabs.len()
+        } else {
+            (self.active + self.tabs.len() - 1) % self.tabs.len()
+        };
+    }
+
+    /// Closes the active tab, returning its id.
+    fn close_active_tab(&mut self) -> Option<TabId> {
+        if self.active >= self.tabs.len() {
+            return None;
+        }
+        let closed = self.tabs.remove(self.active).tab;
+        if self.compare == Some(closed) {
+            self.compare = None;
+        }
+        self.active = self.active.min(self.tabs.len().saturating_sub(1));
+        Some(closed)
+    }
+
+    /// Moves the selection in the active tab by `delta` rows, stopping at either end.
+    fn move_selection(&mut self, delta: isize) {
+        let Some((_, order)) = self
+            .active_tab()
+            .and_then(|result_state| self.table_rows(result_state))
+        else {
+            return;
+        };
+        let last = order.len().saturating_sub(1);
+        let Some(result_state) = self.tabs.get_mut(self.active) else {
+            return;
+        };
+        result_state.selected = result_state.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Selects the row shown at `index` in the active tab, if there is one.
+    fn select(&mut self, index: usize) {
+        let Some((_, order)) = self
+            .active_tab()
+            .and_then(|result_state| self.table_rows(result_state))
+        else {
+            return;
+        };
+        let Some(result_state) = self.tabs.get_mut(self.active) else {
+            return;
+        };
+        if index < order.len() {
+            result_state.selected = index;
+        }
+    }
+
+    /// The cells of a tab's results, and the indices of the rows shown in
+    /// the order they are shown in.
+    fn table_rows(&self, result_state: &ResultState) -> Option<(Vec<Vec<String>>, Vec<usize>)> {
+        let response = result_state.value.as_ref()?;
+        let rows = table::rows(response, self.columns.for_response(response));
+        let order = table::row_order(&rows, result_state.sort);
+        let order = table::filter_rows(&rows, order, &result_state.filter);
+        Some((rows, order))
+    }
+
+    /// Shows only the active tab's rows containing `filter`, from the first one.
+    fn set_filter(&mut self, filter: &str) {
+        if let Some(result_state) = self.tabs.get_mut(self.active) {
+            result_state.filter = filter.to_string();
+            result_state.selected = 0;
+        }
+    }
+
+    /// Orders the active tab by a column, reversing the order when it is
+    /// already sorted by it. `None` restores the order of the API.
+    fn sort_by(&mut self, column: Option<usize>) {
+        let Some(result_state) = self.tabs.get_mut(self.active) else {
+            return;
+        };
+        let Some(response) = &result_state.value else {
+            return;
+        };
+        result_state.sort = match column {
+            Some(column) if column >= self.columns.for_response(response).len() => return,
+            Some(column) => match result_state.sort {
+                Some((sorted, descending)) if sorted == column => Some((column, !descending)),
+                _ => Some((column, false)),
+            },
+            None => None,
+        };
+    }
+
+    fn set_detail(&mut self, detail: bool) {
+        if let Some(result_state) = self.tabs.get_mut(self.active) {
+            result_state.detail = detail && result_state.value.is_some();
+        }
+    }
+
+    /// The portrait of the selected character while its details are open.
+    fn wanted_portrait(&self) -> Option<&str> {
+        if self.protocol == Protocol::Off {
+            return None;
+        }
+        let result_state = self.active_tab().filter(|result_state| result_state.detail)?;
+        let Some(RMResponseEnum::Characters(page)) = &result_state.value else {
+            return None;
+        };
+        let (_, order) = self.table_rows(result_state)?;
+        let character = page.results.get(*order.get(result_state.selected)?)?;
+        Some(character.image.as_str()).filter(|url| !url.is_empty())
+    }
+
+    /// Pins the active tab to be compared with whichever tab is active
+    /// next, or unpins it.
+    fn toggle_compare(&mut self) {
+        let active = self.active_tab().map(|result_state| result_state.tab);
+        self.compare = if self.compare.is_some() { None } else { active };
+    }
+
+    pub fn apply(&mut self, event: Event) {
+        match event {
+            Event::Started(tab, query) => {
+                let result_state = ResultState {
+                    tab,
+                    id: query,
+                    value: None,
+                    error: None,
+                    started_at: Instant::now(),
+                    progress: None,
+                    selected: 0,
+                    detail: false,
+                    sort: None,
+                    filter: String::new(),
+                    stats: None,
+                    explanation: None,
+                };
+                match self.tabs.iter().position(|existing| existing.tab == tab) {
+                    Some(index) => self.tabs[index] = result_state,
+                    None => {
+                        self.tabs.push(result_state);
+                        self.active = self.tabs.len() - 1;
+                    }
+                }
+            }
+            Event::Progress(tab, stage) => {
+                if let Some(result_state) = self.tab_mut(tab) {
+                    result_state.progress = Some(stage);
+                }
+            }
+            Event::Completed(tab, response, stats) => {
+                self.record(tab, Some(response.result_count()));
+                if let Some(result_state) = self.tab_mut(tab) {
+                    result_state.value = Some(response);
+                    result_state.stats = Some(stats);
+                }
+            }
+            Event::Explained(tab, explanation) => {
+                if let Some(result_state) = self.tab_mut(tab) {
+                    result_state.explanation = Some(explanation);
+                }
+            }
+            Event::Failed(tab, error) => {
+                if error != RickError::Cancelled {
+                    self.record(tab, None);
+                }
+                if let Some(result_state) = self.tab_mut(tab) {
+                    result_state.error = Some(error);
+                }
+            }
+            Event::Exported(Ok((path, rows))) => {
+                self.message = Some(format!("Exported {} rows to {}", rows, path.display()));
+            }
+            Event::Exported(Err(error)) => {
+                self.message = Some(format!("{}: {}", error.kind(), error));
+            }
+            Event::Portrait(url, image) => {
+                let portrait = match image {
+                    Ok(image) => Portrait::Ready(image),
+                    Err(error) => Portrait::Failed(format!("{}: {}", error.kind(), error)),
+                };
+                self.portraits.insert(url, portrait);
+            }
+            Event::BackendSwitched(Ok(backend)) => {
+                self.message = Some(format!("Switched to the {} backend", backend));
+                self.backend = backend;
+            }
+            Event::Macros(Ok(message)) => self.message = Some(message),
+            Event::BackendSwitched(Err(error)) | Event::Macros(Err(error)) => {
+                self.message = Some(format!("{}: {}", error.kind(), error));
+            }
+        }
+    }
+
+    fn record(&mut self, tab: TabId, result_count: Option<usize>) {
+        let Some(result_state) = self.tab(tab) else {
+            return;
+        };
+        let entry = HistoryEntry {
+            query: result_state.id.clone(),
+            timestamp: chrono::Utc::now(),
+            result_count,
+            duration_ms: result_state.started_at.elapsed().as_millis() as u64,
+        };
+        if let Err(err) = self.history.record(entry) {
+            self.message = Some(format!("could not save history: {}", err));
+        }
+    }
+}
+
+/// Which pane the arrow keys and Enter act on in normal mode
+#[derive(PartialEq)]
+enum Focus {
+    Results,
+    History,
+}
+
+/// How far PageUp and PageDown move the selection
+const PAGE_ROWS: isize = 10;
+
 enum InputMode {
     Normal,
     Editing,
+    /// Reverse searching the history, started with Ctrl-R
+    Searching,
+    /// Typing the text the active tab's rows are filtered by
+    Filtering,
+    /// Typing a command after `:`
+    CommandLine,
 }
 
 pub struct Renderer {
     input: Input,
     input_mode: InputMode,
-    app_state: Arc<Mutex<AppState>>,
-    tx: Sender<String>,
+    app_state: AppState,
+    tx: UnboundedSender<Command>,
+    events: Receiver<Event>,
+    /// The history entry shown in the input by Up/Down, `None` when editing a new query
+    recall: Option<usize>,
+    /// What was typed before recalling, restored when going past the newest entry
+    draft: String,
+    search: Input,
+    search_match: Option<usize>,
+    /// The filter being typed after `/`
+    filter: Input,
+    /// The command being typed after `:`
+    command_line: Input,
+    /// Selected row of the history pane, counted from the newest entry
+    history_selected: usize,
+    focus: Focus,
+    /// Candidates offered by the last Tab, cycled by pressing it again
+    completion: Option<CompletionState>,
+    /// Whether the keybinding overlay is open
+    show_help: bool,
+    /// The portrait drawn by the last frame and where, for protocols that
+    /// draw it with escape sequences after the cells
+    placement: RefCell<Option<(String, Rect)>>,
+    /// Where the last frame drew the active tab's table, to find what a click hits
+    table_area: RefCell<Option<TableArea>>,
+    keymap: Keymap,
+}
+
+/// Where a tab's table was drawn.
+#[derive(Debug, Clone, PartialEq)]
+struct TableArea {
+    /// The line of column names
+    header: u16,
+    /// The left edge and width of every column
+    columns: Vec<(u16, u16)>,
+    /// The lines the results are drawn on, the first one showing the
+    /// result at `offset`
+    rows: Rect,
+    offset: usize,
+}
+
+impl TableArea {
+    /// The column whose name is drawn at a cell.
+    fn column_at(&self, column: u16, row: u16) -> Option<usize> {
+        if row != self.header {
+            return None;
+        }
+        self.columns
+            .iter()
+            .position(|(left, width)| (*left..left + width).contains(&column))
+    }
+
+    /// The position in display order of the result drawn at a cell.
+    fn row_at(&self, column: u16, row: u16) -> Option<usize> {
+        let inside = (self.rows.left()..self.rows.right()).contains(&column)
+            && (self.rows.top()..self.rows.bottom()).contains(&row);
+        inside.then(|| self.offset + (row - self.rows.y) as usize)
+    }
+}
+
+/// What drawing a tab put where, for what happens after the frame.
+#[derive(Default)]
+struct Drawn {
+    /// A portrait to draw with escape sequences, and where
+    portrait: Option<(String, Rect)>,
+    table: Option<TableArea>,
+}
+
+struct CompletionState {
+    /// The input before and after the word being completed
+    prefix: String,
+    suffix: String,
+    candidates: Vec<String>,
+    index: usize,
 }
 
 impl Renderer {
-    pub fn new(tx: Sender<String>, app_state: Arc<Mutex<AppState>>) -> Self {
+    pub fn new(
+        tx: UnboundedSender<Command>,
+        events: Receiver<Event>,
+        history: History,
+        columns: Columns,
+        backend: String,
+    ) -> Self {
         Self {
             input_mode: InputMode::Editing,
             input: Input::default(),
-            app_state,
+            app_state: AppState::new(history, columns, backend),
             tx,
+            events,
+            recall: None,
+            draft: String::new(),
+            search: Input::default(),
+            search_match: None,
+            filter: Input::default(),
+            command_line: Input::default(),
+            history_selected: 0,
+            focus: Focus::Results,
+            completion: None,
+            show_help: false,
+            placement: RefCell::new(None),
+            table_area: RefCell::new(None),
+            keymap: Keymap::default(),
         }
     }
 
+    /// Shows the portraits of characters in their details, drawn with `protocol`.
+    pub fn with_portraits(mut self, protocol: Protocol) -> Self {
+        self.app_state.protocol = protocol;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.app_state.theme = theme;
+        self
+    }
+
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
     pub fn start(self) -> Result<(), Box<dyn Error>> {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
@@ -73,40 +511,390 @@ impl Renderer {
 
         Ok(())
     }
-}
 
-// pub fn init_app(tx: Sender<String>) -> Result<(), Box<dyn Error>> {
+    /// Applies every event the engine sent since the last call.
+    fn drain_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            self.app_state.apply(event);
+        }
+    }
 
-// }
+    /// Asks the engine for the portrait the detail view shows, once per URL.
+    fn request_portrait(&mut self) {
+        let Some(url) = self.app_state.wanted_portrait() else {
+            return;
+        };
+        if !self.app_state.portraits.contains_key(url) {
+            let url = url.to_string();
+            self.app_state.portraits.insert(url.clone(), Portrait::Loading);
+            self.send(Command::FetchPortrait(url));
+        }
+    }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut renderer: Renderer) -> io::Result<()> {
-    let tx = renderer.tx.clone();
-    loop {
-        terminal.draw(|f| ui(f, &mut renderer))?;
+    fn send(&self, command: Command) {
+        // the engine only stops after Quit, so a failed send means we are shutting down anyway
+        let _ = self.tx.send(command);
+    }
 
-        if let Event::Key(key) = event::read()? {
-            match renderer.input_mode {
-                InputMode::Normal => match key.code {
-                    KeyCode::Char('e') => {
-                        renderer.input_mode = InputMode::Editing;
-                    }
-                    KeyCode::Char('q') => {
-                        return Ok(());
-                    }
-                    _ => {}
-                },
-                InputMode::Editing => match key.code {
-                    KeyCode::Enter => {
-                        tx.send(renderer.input.value().to_string()).unwrap();
+    /// Runs a query in a new tab.
+    fn run_query(&mut self, query: String) {
+        self.recall = None;
+        let tab = self.app_state.new_tab_id();
+        self.send(Command::RunQuery(tab, query));
+    }
+
+    /// Sends a command for the active tab, if there is one.
+    fn send_to_active_tab(&self, command: impl FnOnce(TabId) -> Command) {
+        if let Some(result_state) = self.app_state.active_tab() {
+            self.send(command(result_state.tab));
+        }
+    }
+
+    /// Steps through the history like a shell, `older` for Up.
+    fn recall(&mut self, older: bool) {
+        let history = self.app_state.history.entries();
+        let next = match (self.recall, older) {
+            (None, true) if !history.is_empty() => {
+                self.draft = self.input.value().to_string();
+                Some(history.len() - 1)
+            }
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < history.len() => Some(index + 1),
+            (Some(_), false) => None,
+            (None, _) => return,
+        };
+        let value = match next {
+            Some(index) => history[index].query.clone(),
+            None => self.draft.clone(),
+        };
+        self.recall = next;
+        self.input = Input::new(value);
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        match mouse.kind {
+            MouseEventKind::ScrollDown => self.app_state.move_selection(1),
+            MouseEventKind::ScrollUp => self.app_state.move_selection(-1),
+            MouseEventKind::Down(MouseButton::Left) => self.click(mouse.column, mouse.row),
+            _ => {}
+        }
+    }
+
+    /// Sorts by the column whose name was clicked on, or selects the result
+    /// clicked on and opens its details when it already is selected.
+    fn click(&mut self, column: u16, row: u16) {
+        let Some(table) = self.table_area.borrow().clone() else {
+            return;
+        };
+        if let Some(sorted) = table.column_at(column, row) {
+            self.app_state.sort_by(Some(sorted));
+            return;
+        }
+        let Some(index) = table.row_at(column, row) else {
+            return;
+        };
+        self.focus = Focus::Results;
+        let selected = self.app_state.active_tab().map(|result_state| result_state.selected);
+        if selected == Some(index) {
+            self.app_state.set_detail(true);
+        } else {
+            self.app_state.select(index);
+        }
+    }
+
+    /// Completes the word before the cursor, or moves on to the next
+    /// candidate when Tab was the last key pressed.
+    fn complete(&mut self) {
+        let state = match self.completion.take() {
+            Some(state) => CompletionState {
+                index: (state.index + 1) % state.candidates.len(),
+                ..state
+            },
+            None => {
+                let value = self.input.value();
+                let cursor = value
+                    .char_indices()
+                    .nth(self.input.cursor())
+                    .map(|(index, _)| index)
+                    .unwrap_or(value.len());
+                let completion = complete(value, cursor);
+                if completion.candidates.is_empty() {
+                    return;
+                }
+                CompletionState {
+                    prefix: value[..completion.start].to_string(),
+                    suffix: value[cursor..].to_string(),
+                    candidates: completion.candidates,
+                    index: 0,
+                }
+            }
+        };
+        let completed = format!("{}{}", state.prefix, state.candidates[state.index]);
+        let cursor = completed.chars().count();
+        self.input = Input::new(format!("{}{}", completed, state.suffix)).with_cursor(cursor);
+        self.completion = Some(state);
+    }
+
+    /// Runs a normal mode action, returning false once the user asked to quit.
+    fn act(&mut self, action: Action) -> bool {
+        match action {
+            Action::Edit => self.input_mode = InputMode::Editing,
+            Action::NextPage => self.send_to_active_tab(Command::NextPage),
+            Action::PrevPage => self.send_to_active_tab(Command::PrevPage),
+            Action::Cancel => self.send_to_active_tab(Command::Cancel),
+            Action::Export => self.send_to_active_tab(|tab| {
+                let format = ExportFormat::Csv;
+                Command::Export(tab, format, command_line::default_export_path(format))
+            }),
+            Action::CloseTab => {
+                if let Some(tab) = self.app_state.close_active_tab() {
+                    self.send(Command::CloseTab(tab));
+                }
+            }
+            Action::Compare => self.app_state.toggle_compare(),
+            Action::Help => self.show_help = true,
+            Action::Quit => {
+                self.send(Command::Quit);
+                return false;
+            }
+            Action::ToggleHistory => {
+                self.focus = match self.focus {
+                    Focus::Results => Focus::History,
+                    Focus::History => Focus::Results,
+                };
+            }
+            Action::Up if self.focus == Focus::History => {
+                self.history_selected = (self.history_selected + 1)
+                    .min(self.app_state.history.len().saturating_sub(1));
+            }
+            Action::Down if self.focus == Focus::History => {
+                self.history_selected = self.history_selected.saturating_sub(1);
+            }
+            Action::Up => self.app_state.move_selection(-1),
+            Action::Down => self.app_state.move_selection(1),
+            Action::Filter => {
+                let filter = self
+                    .app_state
+                    .active_tab()
+                    .map(|result_state| result_state.filter.clone())
+                    .unwrap_or_default();
+                self.filter = Input::new(filter);
+                self.input_mode = InputMode::Filtering;
+            }
+            Action::CommandLine => {
+                self.command_line = Input::default();
+                self.input_mode = InputMode::CommandLine;
+            }
+        }
+        true
+    }
+
+    /// Runs a command typed after `:`, returning false once it asked to quit.
+    fn run_line(&mut self, line: &str) -> bool {
+        let command = match command_line::parse(line) {
+            Ok(command) => command,
+            Err(error) => {
+                self.app_state.message = Some(format!("{}: {}", error.kind(), error));
+                return true;
+            }
+        };
+        match command {
+            LineCommand::Export(format, path) => {
+                self.send_to_active_tab(|tab| Command::Export(tab, format, path));
+            }
+            LineCommand::Backend(mode) => self.send(Command::SwitchBackend(mode)),
+            LineCommand::History => self.focus = Focus::History,
+            LineCommand::SaveMacro(signature, query) => {
+                let query = query.or_else(|| {
+                    self.app_state
+                        .active_tab()
+                        .map(|result_state| result_state.id.clone())
+                });
+                match query {
+                    Some(query) => self.send(Command::SaveMacro(signature, query)),
+                    None => self.app_state.message = Some("there is no query to save".to_string()),
+                }
+            }
+            LineCommand::RemoveMacro(name) => self.send(Command::RemoveMacro(name)),
+            LineCommand::ListMacros => self.send(Command::ListMacros),
+            LineCommand::Help => return self.act(Action::Help),
+            LineCommand::CloseTab => return self.act(Action::CloseTab),
+            LineCommand::Quit => return self.act(Action::Quit),
+        }
+        true
+    }
+
+    /// The normal mode keys that cannot be rebound.
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Tab | KeyCode::Right => self.app_state.switch_tab(true),
+            KeyCode::BackTab | KeyCode::Left => self.app_state.switch_tab(false),
+            KeyCode::Up => {
+                self.act(Action::Up);
+            }
+            KeyCode::Down => {
+                self.act(Action::Down);
+            }
+            KeyCode::PageUp => self.app_state.move_selection(-PAGE_ROWS),
+            KeyCode::PageDown => self.app_state.move_selection(PAGE_ROWS),
+            KeyCode::Home => self.app_state.move_selection(isize::MIN),
+            KeyCode::End => self.app_state.move_selection(isize::MAX),
+            KeyCode::Enter if self.focus == Focus::Results => {
+                let detail = self
+                    .app_state
+                    .active_tab()
+                    .is_some_and(|result_state| result_state.detail);
+                self.app_state.set_detail(!detail);
+            }
+            KeyCode::Esc => self.app_state.set_detail(false),
+            KeyCode::Char('0') => self.app_state.sort_by(None),
+            KeyCode::Char(digit @ '1'..='9') => {
+                self.app_state.sort_by(digit.to_digit(10).map(|n| n as usize - 1));
+            }
+            KeyCode::Enter => {
+                let history = self.app_state.history.entries();
+                if self.history_selected < history.len() {
+                    let query = history[history.len() - 1 - self.history_selected]
+                        .query
+                        .clone();
+                    self.input = Input::new(query.clone());
+                    self.history_selected = 0;
+                    self.run_query(query);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns false once the user asked to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code != KeyCode::Tab {
+            self.completion = None;
+        }
+        // any key closes the help overlay
+        if self.show_help {
+            self.show_help = false;
+            return true;
+        }
+        match self.input_mode {
+            InputMode::Normal => match self.keymap.action(&key) {
+                Some(action) => return self.act(action),
+                None => self.handle_normal_key(key),
+            },
+            InputMode::Editing if key.code == KeyCode::Tab => self.complete(),
+            InputMode::Editing => match key.code {
+                KeyCode::Enter => {
+                    self.run_query(self.input.value().to_string());
+                }
+                KeyCode::Esc => {
+                    self.input_mode = InputMode::Normal;
+                }
+                KeyCode::Up => self.recall(true),
+                KeyCode::Down => self.recall(false),
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.input_mode = InputMode::Searching;
+                    self.search = Input::default();
+                    self.search_match = None;
+                }
+                _ => {
+                    self.recall = None;
+                    self.input.handle_event(&TermEvent::Key(key));
+                }
+            },
+            InputMode::Filtering => match key.code {
+                KeyCode::Enter => self.input_mode = InputMode::Normal,
+                KeyCode::Esc => {
+                    self.app_state.set_filter("");
+                    self.input_mode = InputMode::Normal;
+                }
+                _ => {
+                    self.filter.handle_event(&TermEvent::Key(key));
+                    self.app_state.set_filter(self.filter.value());
+                }
+            },
+            InputMode::CommandLine => match key.code {
+                KeyCode::Enter => {
+                    self.input_mode = InputMode::Normal;
+                    let line = self.command_line.value().to_string();
+                    return self.run_line(&line);
+                }
+                KeyCode::Esc => self.input_mode = InputMode::Normal,
+                // deleting past the start leaves the command line, like vim
+                KeyCode::Backspace if self.command_line.value().is_empty() => {
+                    self.input_mode = InputMode::Normal;
+                }
+                _ => {
+                    self.command_line.handle_event(&TermEvent::Key(key));
+                }
+            },
+            InputMode::Searching => match key.code {
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    // look further back, staying on the current match when there is none
+                    if let Some(older) = self
+                        .app_state
+                        .history
+                        .search(self.search.value(), self.search_match)
+                    {
+                        self.search_match = Some(older);
                     }
-                    KeyCode::Esc => {
-                        renderer.input_mode = InputMode::Normal;
+                }
+                KeyCode::Enter => {
+                    if let Some(index) = self.search_match {
+                        self.input = Input::new(self.app_state.history.entries()[index].query.clone());
                     }
-                    _ => {
-                        renderer.input.handle_event(&Event::Key(key));
+                    self.input_mode = InputMode::Editing;
+                }
+                KeyCode::Esc => {
+                    self.input_mode = InputMode::Editing;
+                }
+                _ => {
+                    self.search.handle_event(&TermEvent::Key(key));
+                    self.search_match = self.app_state.history.search(self.search.value(), None);
+                }
+            },
+        }
+        true
+    }
+}
+
+fn run_app<B: Backend + Write>(terminal: &mut Terminal<B>, mut renderer: Renderer) -> io::Result<()> {
+    let protocol = renderer.app_state.protocol;
+    let mut shown: Option<(String, Rect)> = None;
+    loop {
+        renderer.drain_events();
+        renderer.request_portrait();
+        terminal.draw(|f| ui(f, &renderer))?;
+
+        // images drawn with escape sequences stay until the screen is cleared,
+        // so they are only redrawn when the portrait or its place changes
+        let placement = renderer.placement.take();
+        if placement != shown {
+            if shown.is_some() {
+                terminal.backend_mut().write_all(protocol.clear_sequence().as_bytes())?;
+                terminal.clear()?;
+                terminal.draw(|f| ui(f, &renderer))?;
+            }
+            if let Some((url, area)) = &placement {
+                if let Some(Portrait::Ready(image)) = renderer.app_state.portraits.get(url) {
+                    if let Some(sequence) = protocol.escape_sequence(image, area.width, area.height) {
+                        let backend = terminal.backend_mut();
+                        queue!(backend, MoveTo(area.x, area.y))?;
+                        backend.write_all(sequence.as_bytes())?;
+                        Write::flush(backend)?;
                     }
-                },
+                }
             }
+            shown = placement;
+        }
+
+        // wake up regularly so results and the loading indicator show up without a key press
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        match event::read()? {
+            TermEvent::Key(key) if !renderer.handle_key(key) => return Ok(()),
+            TermEvent::Mouse(mouse) => renderer.handle_mouse(mouse),
+            _ => {}
         }
     }
 }
@@ -120,18 +908,67 @@ fn ui<B: Backend>(f: &mut Frame<B>, renderer: &Renderer) {
                 Constraint::Length(1),
                 Constraint::Length(3),
                 Constraint::Min(1),
+                Constraint::Length(1),
             ]
             .as_ref(),
         )
         .split(f.size());
 
-    let msg = vec![
-        Span::raw("Press "),
-        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(" then "),
-        Span::styled(" q ", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw("to quit."),
-    ];
+    let theme = &renderer.app_state.theme;
+    let key = |text: &str| Span::styled(text.to_string(), Style::default().add_modifier(Modifier::BOLD));
+    let keymap = &renderer.keymap;
+    let mut msg = match renderer.input_mode {
+        InputMode::Normal => vec![
+            Span::raw("Press "),
+            key(keymap.key(Action::Quit)),
+            Span::raw(" to quit, "),
+            key(keymap.key(Action::Edit)),
+            Span::raw(" to edit, "),
+            key(keymap.key(Action::Help)),
+            Span::raw(" for all keys."),
+        ],
+        InputMode::Editing => vec![
+            Span::raw("Press "),
+            key("Esc"),
+            Span::raw(" to stop editing, "),
+            key("Enter"),
+            Span::raw(" to run, "),
+            key("↑↓"),
+            Span::raw(" to recall, "),
+            key("Tab"),
+            Span::raw(" to complete, "),
+            key("Ctrl-R"),
+            Span::raw(" to search history."),
+        ],
+        InputMode::Searching => vec![
+            Span::raw("Press "),
+            key("Ctrl-R"),
+            Span::raw(" for older matches, "),
+            key("Enter"),
+            Span::raw(" to use the match, "),
+            key("Esc"),
+            Span::raw(" to go back."),
+        ],
+        InputMode::Filtering => vec![
+            Span::raw("Press "),
+            key("Enter"),
+            Span::raw(" to keep the filter, "),
+            key("Esc"),
+            Span::raw(" to clear it."),
+        ],
+        InputMode::CommandLine => vec![
+            Span::raw("Press "),
+            key("Enter"),
+            Span::raw(" to run the command, "),
+            key("Esc"),
+            Span::raw(" to go back, "),
+            key(":help"),
+            Span::raw(" for all commands."),
+        ],
+    };
+    if let Some(message) = &renderer.app_state.message {
+        msg.push(Span::styled(format!("  {}", message), theme.info));
+    }
     let style = Style::default();
 
     let mut text = Text::from(Spans::from(msg));
@@ -140,102 +977,1079 @@ fn ui<B: Backend>(f: &mut Frame<B>, renderer: &Renderer) {
     f.render_widget(help_message, chunks[0]);
 
     let width = chunks[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
-    let scroll = (renderer.input.cursor() as u16).max(width) - width;
-    let input = Paragraph::new(renderer.input.value())
-        .style(Style::default().fg(Color::Yellow))
+    let (title, text, cursor) = match renderer.input_mode {
+        InputMode::Searching => {
+            let matched = renderer
+                .search_match
+                .map(|index| renderer.app_state.history.entries()[index].query.as_str())
+                .unwrap_or("");
+            let title = format!("Search history: {}", renderer.search.value());
+            (title, Spans::from(matched), matched.chars().count())
+        }
+        InputMode::Filtering => (
+            "Filter rows".to_string(),
+            Spans::from(renderer.filter.value()),
+            renderer.filter.cursor(),
+        ),
+        InputMode::CommandLine => (
+            "Command".to_string(),
+            Spans::from(format!(":{}", renderer.command_line.value())),
+            renderer.command_line.cursor() + 1,
+        ),
+        _ => {
+            let title = match &renderer.completion {
+                Some(completion) if completion.candidates.len() > 1 => format!(
+                    "Input (Tab: {})",
+                    completion.candidates.join(" ")
+                ),
+                _ => "Input".to_string(),
+            };
+            let value = renderer.input.value();
+            (title, highlight_query(value, theme), renderer.input.cursor())
+        }
+    };
+    let scroll = (cursor as u16).max(width) - width;
+    let input = Paragraph::new(text)
+        .style(theme.operand)
         .scroll((0, scroll))
-        .block(Block::default().borders(Borders::ALL).title("Input"));
+        .block(Block::default().borders(Borders::ALL).title(title));
     f.render_widget(input, chunks[1]);
     f.set_cursor(
         // Put cursor past the end of the input text
-        chunks[1].x + (renderer.input.cursor() as u16).min(width) + 1,
+        chunks[1].x + (cursor as u16).min(width) + 1,
         // Move one line down, from the border to the input line
         chunks[1].y + 1,
     );
 
-    renderer
-        .app_state
-        .lock()
-        .unwrap()
-        .results
-        .values()
-        .for_each(|result_state|{
-            f.render_widget(create_response_enum_widget(result_state), chunks[2]);
-        });
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
+        .split(chunks[2]);
 
-   
+    let drawn = render_tabs(f, &renderer.app_state, panes[0]);
+    // the overlay would be drawn over by the image
+    *renderer.placement.borrow_mut() = drawn.portrait.filter(|_| !renderer.show_help);
+    *renderer.table_area.borrow_mut() = drawn.table.filter(|_| !renderer.show_help);
+
+    let mut history_state = ListState::default();
+    if matches!(renderer.input_mode, InputMode::Normal)
+        && renderer.focus == Focus::History
+        && !renderer.app_state.history.is_empty()
+    {
+        history_state.select(Some(renderer.history_selected));
+    }
+    f.render_stateful_widget(
+        create_history_widget(&renderer.app_state.history, theme),
+        panes[1],
+        &mut history_state,
+    );
+
+    f.render_widget(create_status_bar(renderer), chunks[3]);
+    if renderer.show_help {
+        let area = centered(f.size(), 80, 90);
+        f.render_widget(Clear, area);
+        f.render_widget(create_help_widget(theme, keymap), area);
+    }
 }
 
-fn create_response_enum_widget(result_state: &ResultState) -> List {
-    let response = result_state.value.clone();
+/// What the keys of the keymap do in normal mode, for the help overlay
+const ACTIONS: &[(Action, &str)] = &[
+    (Action::Edit, "edit the query"),
+    (Action::Quit, "quit"),
+    (Action::CloseTab, "close the tab"),
+    (Action::Compare, "pin the tab to compare it with the next one"),
+    (Action::Up, "select the previous result or history entry"),
+    (Action::Down, "select the next result or history entry"),
+    (Action::ToggleHistory, "move between the results and the history"),
+    (Action::NextPage, "next page"),
+    (Action::PrevPage, "previous page"),
+    (Action::Cancel, "cancel the query that is loading"),
+    (Action::Export, "export the tab to rick_cli_export.csv"),
+    (Action::Filter, "show only the rows containing some text"),
+    (Action::CommandLine, "type a command"),
+    (Action::Help, "show this help"),
+];
 
-    match response {
-        None => List::new(vec![ListItem::new(vec![Spans::from(Span::raw("None"))])]),
-        Some(RMResponseEnum::Characters(characters_page)) => {
-            let characters: Vec<ListItem> = characters_page
-                .results
-                .iter()
-                .map(|character| {
-                    let name_span =
-                        Span::styled(character.name.clone(), Style::default().fg(Color::Green));
-                    let id_span = Span::styled(
-                        format!("({}) ", character.id),
-                        Style::default().fg(Color::Yellow),
-                    );
-                    let status_span = match character.status.as_str() {
-                        "Alive" => Span::styled(
-                            format!(" [{}] ", character.status),
-                            Style::default().fg(Color::Green),
-                        ),
-                        "Dead" => Span::styled(
-                            format!(" [{}] ", character.status),
-                            Style::default().fg(Color::Red),
-                        ),
-                        _ => Span::styled(
-                            format!(" [{}] ", character.status),
-                            Style::default().fg(Color::Yellow),
-                        ),
-                      
-                    };
-                    ListItem::new(vec![Spans::from(vec![id_span, name_span, status_span])])
-                })
-                .collect();
+/// The keys that cannot be rebound, by the mode they work in
+const KEYBINDINGS: &[(&str, &str, &str)] = &[
+    ("normal", "Tab / Shift-Tab, Right / Left", "switch tabs"),
+    ("normal", "Up / Down", "select a result or history entry"),
+    ("normal", "PageUp / PageDown, Home / End", "jump through the results"),
+    ("normal", "Enter", "show the selected result's fields, or re-run a history entry"),
+    ("normal", "Click", "select a result, again to show its fields"),
+    ("normal", "Esc", "hide the fields"),
+    ("normal", "1-9 / click a column name, 0", "sort by a column, again to reverse; 0 for the API order"),
+    ("editing", "Enter", "run the query in a new tab"),
+    ("editing", "Esc", "stop editing"),
+    ("editing", "Tab", "complete, again for the next candidate"),
+    ("editing", "Up / Down", "recall previous queries"),
+    ("editing", "Ctrl-R", "search the history"),
+    ("search", "Ctrl-R", "older match"),
+    ("search", "Enter / Esc", "use the match / go back"),
+    ("filter", "Enter / Esc", "keep the filter / clear it"),
+    ("command", "Enter / Esc", "run the command / go back"),
+];
+
+fn create_help_widget(theme: &Theme, keymap: &Keymap) -> Paragraph<'static> {
+    let actions = ACTIONS
+        .iter()
+        .map(|(action, description)| ("normal", keymap.key(*action), *description));
+    let commands = COMMANDS
+        .iter()
+        .map(|(command, description)| ("command", *command, *description));
+    let lines: Vec<Spans> = actions
+        .chain(KEYBINDINGS.iter().copied())
+        .chain(commands)
+        .map(|(mode, keys, description)| {
+            Spans::from(vec![
+                Span::styled(format!("{:<8}", mode), theme.muted),
+                Span::styled(format!("{:<32}", keys), theme.accent),
+                Span::raw(description),
+            ])
+        })
+        .collect();
+    Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Keys (any key to close)"),
+    )
+}
 
-            List::new(characters).block(Block::default().borders(Borders::ALL).title("Characters"))
+/// A rectangle of the given percentages of `area`, centered in it.
+fn centered(area: Rect, width_percent: u16, height_percent: u16) -> Rect {
+    let width = area.width * width_percent / 100;
+    let height = area.height * height_percent / 100;
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Mode, backend and how the active tab's query went.
+fn create_status_bar(renderer: &Renderer) -> Paragraph<'_> {
+    let app_state = &renderer.app_state;
+    let mode = match renderer.input_mode {
+        InputMode::Normal => " NORMAL ",
+        InputMode::Editing => " EDITING ",
+        InputMode::Searching => " SEARCH ",
+        InputMode::Filtering => " FILTER ",
+        InputMode::CommandLine => " COMMAND ",
+    };
+    let mut parts = vec![app_state.backend.clone()];
+    if let Some(result_state) = app_state.active_tab() {
+        parts.push(format!("tab {}/{}", app_state.active + 1, app_state.tabs.len()));
+        match (&result_state.stats, &result_state.error) {
+            _ if result_state.explanation.is_some() => parts.push("explained".to_string()),
+            (Some(stats), _) => {
+                if stats.fetched == stats.shown {
+                    parts.push(format!("{} rows", stats.shown));
+                } else {
+                    parts.push(format!("{} → {} rows", stats.fetched, stats.shown));
+                }
+                parts.push(format!("{:.2}s", stats.elapsed.as_secs_f32()));
+                parts.push(if stats.from_cache { "cached" } else { "fresh" }.to_string());
+            }
+            (None, Some(error)) => parts.push(error.kind().to_string()),
+            (None, None) => parts.push(format!(
+                "loading {:.1}s",
+                result_state.started_at.elapsed().as_secs_f32()
+            )),
         }
-        Some(RMResponseEnum::Episodes(episodes_page)) => {
-            let episodes: Vec<ListItem> = episodes_page
-                .results
-                .iter()
-                .map(|episode| {
-                    let name_span =
-                        Span::styled(episode.name.clone(), Style::default().fg(Color::Green));
-                    let id_span = Span::styled(
-                        format!("({})", episode.id),
-                        Style::default().fg(Color::Yellow),
-                    );
-                    ListItem::new(vec![Spans::from(vec![name_span, id_span])])
-                })
-                .collect();
+    }
+    Paragraph::new(Spans::from(vec![
+        Span::styled(mode, app_state.theme.status),
+        Span::raw(" "),
+        Span::raw(parts.join(" │ ")),
+    ]))
+}
+
+/// Colours a query by token, underlining the segments that do not parse in red.
+fn highlight_query<'a>(query: &'a str, theme: &Theme) -> Spans<'a> {
+    let spans: Vec<Span> = highlight(query)
+        .into_iter()
+        .map(|token| {
+            let style = match token.kind {
+                TokenKind::Root => theme.root,
+                TokenKind::Operation => theme.operation,
+                TokenKind::Operand => theme.operand,
+                TokenKind::Punctuation | TokenKind::Separator => theme.muted,
+                TokenKind::Invalid => theme.error.add_modifier(Modifier::UNDERLINED),
+            };
+            Span::styled(&query[token.range], style)
+        })
+        .collect();
+    Spans::from(spans)
+}
 
-            List::new(episodes).block(Block::default().borders(Borders::ALL).title("Episodes"))
+/// Draws the tab bar and the active tab, beside the pinned tab when comparing.
+fn render_tabs<B: Backend>(f: &mut Frame<B>, app_state: &AppState, area: Rect) -> Drawn {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
+        .split(area);
+
+    let titles = app_state
+        .tabs
+        .iter()
+        .map(|result_state| {
+            let pinned = if app_state.compare == Some(result_state.tab) { "*" } else { "" };
+            Spans::from(format!("{}{}", pinned, result_state.id))
+        })
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(app_state.active)
+        .highlight_style(app_state.theme.accent);
+    f.render_widget(tabs, areas[0]);
+
+    let Some(active) = app_state.active_tab() else {
+        return Drawn::default();
+    };
+    match app_state
+        .compare
+        .filter(|pinned| *pinned != active.tab)
+        .and_then(|pinned| app_state.tab(pinned))
+    {
+        Some(pinned) => {
+            let sides = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(areas[1]);
+            let pinned = render_result(f, app_state, pinned, sides[0]);
+            let active = render_result(f, app_state, active, sides[1]);
+            Drawn {
+                portrait: active.portrait.or(pinned.portrait),
+                table: active.table,
+            }
         }
-        Some(RMResponseEnum::Locations(locations_page)) => {
-            let locations: Vec<ListItem> = locations_page
-                .results
+        None => render_result(f, app_state, active, areas[1]),
+    }
+}
+
+/// Draws a tab's results as a table with the selected row highlighted, and
+/// its fields beside it when the detail view is open.
+fn render_result<B: Backend>(
+    f: &mut Frame<B>,
+    app_state: &AppState,
+    result_state: &ResultState,
+    area: Rect,
+) -> Drawn {
+    if let Some(explanation) = &result_state.explanation {
+        let explanation = Paragraph::new(explanation.as_str())
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title(result_state.id.as_str()));
+        f.render_widget(explanation, area);
+        return Drawn::default();
+    }
+    let Some(response) = &result_state.value else {
+        f.render_widget(create_status_widget(result_state, &app_state.theme), area);
+        return Drawn::default();
+    };
+    let theme = &app_state.theme;
+    let columns = app_state.columns.for_response(response);
+    let Some((rows, order)) = app_state.table_rows(result_state) else {
+        return Drawn::default();
+    };
+
+    let (table_area, detail_area) = if result_state.detail {
+        let sides = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(area);
+        (sides[0], Some(sides[1]))
+    } else {
+        (area, None)
+    };
+
+    let header: Vec<String> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| match result_state.sort {
+            Some((sorted, false)) if sorted == index => format!("{} ▲", column),
+            Some((sorted, true)) if sorted == index => format!("{} ▼", column),
+            _ => column.clone(),
+        })
+        .collect();
+    // borders, the highlight symbol and the spacing between columns
+    let chrome = 2 + HIGHLIGHT_SYMBOL.chars().count() as u16 + columns.len() as u16 - 1;
+    let column_widths =
+        table::column_widths(&header, &rows, table_area.width.saturating_sub(chrome));
+    let widths: Vec<Constraint> = column_widths.iter().copied().map(Constraint::Length).collect();
+
+    let entity = match response {
+        RMResponseEnum::Characters(_) => "Characters",
+        RMResponseEnum::Episodes(_) => "Episodes",
+        RMResponseEnum::Locations(_) => "Locations",
+    };
+    let mut title = format!("{} - {}", entity, page_position(&result_state.id, response));
+    if !result_state.filter.is_empty() {
+        title.push_str(&format!(
+            " - {} of {} matching \"{}\"",
+            order.len(),
+            rows.len(),
+            result_state.filter
+        ));
+    }
+    let table = Table::new(order.iter().map(|index| {
+        Row::new(
+            rows[*index]
                 .iter()
-                .map(|location| {
-                    let name_span =
-                        Span::styled(location.name.clone(), Style::default().fg(Color::Green));
-                    let id_span = Span::styled(
-                        format!("({})", location.id),
-                        Style::default().fg(Color::Yellow),
-                    );
-                    ListItem::new(vec![Spans::from(vec![name_span, id_span])])
+                .zip(columns)
+                .map(|(value, column)| Cell::from(value.as_str()).style(theme.cell_style(column, value))),
+        )
+    }))
+    .header(Row::new(header).style(theme.accent))
+    .block(Block::default().borders(Borders::ALL).title(title))
+    .widths(&widths)
+    .column_spacing(1)
+    .highlight_symbol(HIGHLIGHT_SYMBOL)
+    .highlight_style(theme.selection);
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(result_state.selected));
+    f.render_stateful_widget(table, table_area, &mut table_state);
+
+    // inside the borders and below the header
+    let rows_area = Rect {
+        x: table_area.x + 1,
+        y: table_area.y + 2,
+        width: table_area.width.saturating_sub(2),
+        height: table_area.height.saturating_sub(3),
+    };
+    // a new TableState starts at the top and scrolls just enough to show the selection
+    let offset = (result_state.selected.min(order.len().saturating_sub(1)) + 1)
+        .saturating_sub(rows_area.height as usize);
+    let mut left = rows_area.x + HIGHLIGHT_SYMBOL.chars().count() as u16;
+    let column_areas = column_widths
+        .iter()
+        .map(|width| {
+            let column = (left, *width);
+            left += width + 1;
+            column
+        })
+        .collect();
+    Drawn {
+        portrait: detail_area.and_then(|detail_area| {
+            let index = *order.get(result_state.selected)?;
+            render_detail(f, app_state, response, index, detail_area)
+        }),
+        table: Some(TableArea {
+            header: table_area.y + 1,
+            columns: column_areas,
+            rows: rows_area,
+            offset,
+        }),
+    }
+}
+
+/// Draws the fields of the result at `index`, under its portrait when it has one.
+/// Returns where a portrait is to be drawn with escape sequences, if anywhere.
+fn render_detail<B: Backend>(
+    f: &mut Frame<B>,
+    app_state: &AppState,
+    response: &RMResponseEnum,
+    index: usize,
+    detail_area: Rect,
+) -> Option<(String, Rect)> {
+    let theme = &app_state.theme;
+    let fields = selected_fields(response, index)?;
+    let image = match response {
+        RMResponseEnum::Characters(page) if app_state.protocol != Protocol::Off => {
+            Some(page.results[index].image.as_str()).filter(|url| !url.is_empty())
+        }
+        _ => None,
+    };
+    let Some(url) = image else {
+        f.render_widget(create_detail_widget(fields, theme), detail_area);
+        return None;
+    };
+
+    // a square portrait above the fields, two columns per row as cells are about twice as tall as wide
+    let height = (detail_area.height / 2).min(detail_area.width.saturating_sub(2) / 2 + 2);
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(height), Constraint::Min(1)].as_ref())
+        .split(detail_area);
+    f.render_widget(create_detail_widget(fields, theme), areas[1]);
+    render_portrait(f, app_state, url, areas[0])
+}
+
+/// Draws a portrait with half blocks, or leaves its place empty for the
+/// protocols that draw it after the frame.
+fn render_portrait<B: Backend>(
+    f: &mut Frame<B>,
+    app_state: &AppState,
+    url: &str,
+    area: Rect,
+) -> Option<(String, Rect)> {
+    let block = Block::default().borders(Borders::ALL).title("Portrait");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let image = match app_state.portraits.get(url) {
+        Some(Portrait::Ready(image)) => image,
+        Some(Portrait::Failed(message)) => {
+            let message = Paragraph::new(message.as_str())
+                .style(app_state.theme.error)
+                .wrap(Wrap { trim: false });
+            f.render_widget(message, inner);
+            return None;
+        }
+        Some(Portrait::Loading) | None => {
+            f.render_widget(Paragraph::new("Loading portrait..."), inner);
+            return None;
+        }
+    };
+    if app_state.protocol == Protocol::Blocks {
+        f.render_widget(HalfBlocks(image), inner);
+        return None;
+    }
+    let width = inner.width.min(inner.height * 2);
+    let area = Rect {
+        x: inner.x + (inner.width - width) / 2,
+        width,
+        ..inner
+    };
+    Some((url.to_string(), area))
+}
+
+/// "page x of y, N total", with the page taken from the last query of the plan and the rest from `Info`.
+pub fn page_position(query: &str, response: &RMResponseEnum) -> String {
+    let info = response.info();
+    if info.count == 0 {
+        return "no results".to_string();
+    }
+    let page = Plan::parse(query).and_then(|plan| plan.page()).unwrap_or(1);
+    format!("page {} of {}, {} total", page, info.pages, info.count)
+}
+
+fn selected_fields(response: &RMResponseEnum, index: usize) -> Option<Vec<(&'static str, String)>> {
+    match response {
+        RMResponseEnum::Characters(page) => page.results.get(index).map(Entity::fields),
+        RMResponseEnum::Episodes(page) => page.results.get(index).map(Entity::fields),
+        RMResponseEnum::Locations(page) => page.results.get(index).map(Entity::fields),
+    }
+}
+
+fn create_detail_widget(fields: Vec<(&'static str, String)>, theme: &Theme) -> Paragraph<'static> {
+    let lines: Vec<Spans> = fields
+        .into_iter()
+        .map(|(name, value)| {
+            let style = theme.cell_style(name, &value);
+            Spans::from(vec![
+                Span::styled(format!("{}: ", name), theme.accent),
+                Span::styled(value, style),
+            ])
+        })
+        .collect();
+    Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Details"))
+}
+
+/// Lists previous queries, newest first.
+fn create_history_widget<'a>(history: &'a History, theme: &Theme) -> List<'a> {
+    let entries: Vec<ListItem> = history
+        .entries()
+        .iter()
+        .rev()
+        .map(|entry| {
+            let outcome = match entry.result_count {
+                Some(count) => format!("{} results", count),
+                None => "failed".to_string(),
+            };
+            ListItem::new(vec![
+                Spans::from(Span::styled(entry.query.clone(), theme.query)),
+                Spans::from(Span::styled(
+                    format!(
+                        "  {} · {:.1}s · {}",
+                        outcome,
+                        entry.duration_ms as f32 / 1000.0,
+                        entry.timestamp.with_timezone(&chrono::Local).format("%b %d %H:%M")
+                    ),
+                    theme.muted,
+                )),
+            ])
+        })
+        .collect();
+
+    List::new(entries)
+        .block(Block::default().borders(Borders::ALL).title("History"))
+        .highlight_style(theme.selection)
+}
+
+const HIGHLIGHT_SYMBOL: &str = "> ";
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Shows that a tab is loading, or why its query failed.
+fn create_status_widget<'a>(result_state: &'a ResultState, theme: &Theme) -> List<'a> {
+    let message = match &result_state.error {
+        Some(error) => {
+            // mistakes in the query are the user's to fix, everything else comes from the API or network
+            let style = match error {
+                RickError::Parse(_) | RickError::UnknownField { .. } | RickError::Invalid(_) => {
+                    theme.warning
+                }
+                RickError::Cancelled | RickError::NotRecorded(_) => theme.muted,
+                _ => theme.error,
+            };
+            Spans::from(vec![
+                Span::styled(
+                    format!("{}: ", error.kind()),
+                    style.add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(error.to_string(), style),
+            ])
+        }
+        None => {
+            let elapsed = result_state.started_at.elapsed();
+            let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+            let stage = result_state.progress.as_deref().unwrap_or("starting");
+            Spans::from(Span::styled(
+                format!("{} Loading ({})... {:.1}s", frame, stage, elapsed.as_secs_f32()),
+                theme.info,
+            ))
+        }
+    };
+    List::new(vec![ListItem::new(vec![message])])
+        .block(Block::default().borders(Borders::ALL).title(result_state.id.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::{entities::*, response::RMResponseEnum};
+    use std::sync::mpsc;
+    use tui::{backend::TestBackend, style::Color};
+
+    fn press(renderer: &mut Renderer, code: KeyCode) -> bool {
+        renderer.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn click(renderer: &mut Renderer, column: u16, row: u16) {
+        renderer.handle_mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        });
+    }
+
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol.as_str()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn drives_the_ui_headlessly() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+
+        for c in "EPISODES".chars() {
+            press(&mut renderer, KeyCode::Char(c));
+        }
+        press(&mut renderer, KeyCode::Enter);
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::RunQuery(1, "EPISODES".into())
+        );
+
+        events_tx.send(Event::Started(1, "EPISODES".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage {
+                    results: vec![Episode {
+                        id: 1,
+                        name: "Pilot".into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                QueryStats::default(),
+            ))
+            .unwrap();
+        renderer.drain_events();
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(screen(&terminal).contains("Pilot"));
+
+        assert!(screen(&terminal).contains("EPISODES"));
+        assert_eq!(renderer.app_state.history.entries()[0].result_count, Some(1));
+
+        press(&mut renderer, KeyCode::Esc);
+        assert!(!press(&mut renderer, KeyCode::Char('q')));
+        assert_eq!(commands.try_recv().unwrap(), Command::Quit);
+    }
+
+    #[test]
+    fn recalls_and_searches_history() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (_events_tx, events) = mpsc::channel();
+        let mut history = History::in_memory();
+        for query in ["CHARACTERS::NAME(rick)", "EPISODES", "LOCATIONS"] {
+            history
+                .record(HistoryEntry {
+                    query: query.into(),
+                    timestamp: chrono::Utc::now(),
+                    result_count: Some(1),
+                    duration_ms: 10,
                 })
-                .collect();
+                .unwrap();
+        }
+        let mut renderer = Renderer::new(tx, events, history, Columns::default(), "Mock".into());
+
+        press(&mut renderer, KeyCode::Char('C'));
+        press(&mut renderer, KeyCode::Up);
+        press(&mut renderer, KeyCode::Up);
+        assert_eq!(renderer.input.value(), "EPISODES");
+        press(&mut renderer, KeyCode::Down);
+        press(&mut renderer, KeyCode::Down);
+        assert_eq!(renderer.input.value(), "C");
 
-            List::new(locations).block(Block::default().borders(Borders::ALL).title("Locations"))
+        renderer.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        press(&mut renderer, KeyCode::Char('N'));
+        assert_eq!(renderer.search_match, Some(2));
+        renderer.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert_eq!(renderer.search_match, Some(0));
+        press(&mut renderer, KeyCode::Enter);
+        assert_eq!(renderer.input.value(), "CHARACTERS::NAME(rick)");
+
+        press(&mut renderer, KeyCode::Esc);
+        press(&mut renderer, KeyCode::Char('h'));
+        press(&mut renderer, KeyCode::Up);
+        press(&mut renderer, KeyCode::Enter);
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::RunQuery(1, "EPISODES".into())
+        );
+    }
+
+    #[test]
+    fn completes_and_highlights_the_query() {
+        let (tx, _commands) = tokio::sync::mpsc::unbounded_channel();
+        let (_events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+
+        for c in "LOCATIONS::SORT(ASC, ".chars() {
+            press(&mut renderer, KeyCode::Char(c));
         }
+        press(&mut renderer, KeyCode::Tab);
+        assert_eq!(renderer.input.value(), "LOCATIONS::SORT(ASC, id");
+        press(&mut renderer, KeyCode::Tab);
+        assert_eq!(renderer.input.value(), "LOCATIONS::SORT(ASC, name");
+        press(&mut renderer, KeyCode::Char(')'));
+        assert_eq!(renderer.input.value(), "LOCATIONS::SORT(ASC, name)");
+
+        let spans = highlight_query("EPISODES::NAEM(x)", &Theme::default());
+        let invalid = spans.0.last().unwrap();
+        assert_eq!(invalid.content, "NAEM(x)");
+        assert!(invalid.style.add_modifier.contains(Modifier::UNDERLINED));
+    }
+
+    #[test]
+    fn shows_status_and_help() {
+        let (tx, _commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Direct".into());
+        events_tx.send(Event::Started(1, "EPISODES::LIMIT(2)".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage::default()),
+                QueryStats {
+                    fetched: 20,
+                    shown: 2,
+                    from_cache: true,
+                    elapsed: Duration::from_millis(420),
+                },
+            ))
+            .unwrap();
+        renderer.drain_events();
+        press(&mut renderer, KeyCode::Esc);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(screen(&terminal)
+            .contains(" NORMAL  Direct │ tab 1/1 │ 20 → 2 rows │ 0.42s │ cached"));
+
+        press(&mut renderer, KeyCode::Char('?'));
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(screen(&terminal).contains("search the history"));
+        // the key closing the overlay does nothing else
+        assert!(press(&mut renderer, KeyCode::Char('q')));
+        assert!(!renderer.show_help);
+    }
+
+    #[test]
+    fn pages_through_results() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+        let response = RMResponseEnum::Characters(CharacterPage {
+            info: Info {
+                count: 826,
+                pages: 42,
+                next: Some("https://rickandmortyapi.com/api/character?page=3".into()),
+                prev: Some("https://rickandmortyapi.com/api/character?page=1".into()),
+            },
+            ..Default::default()
+        });
+        assert_eq!(
+            page_position("CHARACTERS::PAGE(2)", &response),
+            "page 2 of 42, 826 total"
+        );
+
+        events_tx
+            .send(Event::Started(1, "CHARACTERS::PAGE(2)".into()))
+            .unwrap();
+        events_tx
+            .send(Event::Completed(1, response, QueryStats::default()))
+            .unwrap();
+        renderer.drain_events();
+        press(&mut renderer, KeyCode::Esc);
+        press(&mut renderer, KeyCode::Char('p'));
+        press(&mut renderer, KeyCode::Char('n'));
+        assert_eq!(commands.try_recv().unwrap(), Command::PrevPage(1));
+        assert_eq!(commands.try_recv().unwrap(), Command::NextPage(1));
+    }
+
+    #[test]
+    fn scrolls_and_opens_details() {
+        let (tx, _commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+        let episodes = (1..=30)
+            .map(|id| Episode {
+                id,
+                name: format!("Episode {}", id),
+                episode: format!("S01E{:02}", id),
+                ..Default::default()
+            })
+            .collect();
+        events_tx.send(Event::Started(1, "EPISODES".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage {
+                    results: episodes,
+                    ..Default::default()
+                }),
+                QueryStats::default(),
+            ))
+            .unwrap();
+        renderer.drain_events();
+
+        press(&mut renderer, KeyCode::Esc);
+        press(&mut renderer, KeyCode::End);
+        press(&mut renderer, KeyCode::Down);
+        assert_eq!(renderer.app_state.tabs[0].selected, 29);
+        renderer.handle_mouse(MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            column: 0,
+            row: 0,
+            modifiers: KeyModifiers::NONE,
+        });
+        press(&mut renderer, KeyCode::Enter);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 16)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let screen = screen(&terminal);
+        assert!(screen.contains("> 29"));
+        assert!(screen.contains("episode: S01E29"));
+
+        press(&mut renderer, KeyCode::Esc);
+        assert!(!renderer.app_state.tabs[0].detail);
+
+        press(&mut renderer, KeyCode::Char('1'));
+        press(&mut renderer, KeyCode::Char('1'));
+        press(&mut renderer, KeyCode::Home);
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let screen = super::tests::screen(&terminal);
+        assert!(screen.contains("id ▼"));
+        assert!(screen.contains("> 30"));
+
+        // the rows start below the tab bar, the border and the header, on line 9
+        click(&mut renderer, 10, 10);
+        assert_eq!(renderer.app_state.tabs[0].selected, 1);
+        assert!(!renderer.app_state.tabs[0].detail);
+        click(&mut renderer, 10, 10);
+        assert!(renderer.app_state.tabs[0].detail);
+        press(&mut renderer, KeyCode::Esc);
+
+        // three rows fit, so the last one scrolls the table by 27
+        press(&mut renderer, KeyCode::End);
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        click(&mut renderer, 10, 9);
+        assert_eq!(renderer.app_state.tabs[0].selected, 27);
+        click(&mut renderer, 10, 13);
+        assert_eq!(renderer.app_state.tabs[0].selected, 27);
+    }
+
+    #[test]
+    fn sorts_by_clicked_headers() {
+        let (tx, _commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+        let episodes = [(2, "Lawnmower Dog"), (3, "Anatomy Park"), (1, "Pilot")]
+            .into_iter()
+            .map(|(id, name)| Episode {
+                id,
+                name: name.into(),
+                ..Default::default()
+            })
+            .collect();
+        events_tx.send(Event::Started(1, "EPISODES".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage {
+                    results: episodes,
+                    ..Default::default()
+                }),
+                QueryStats::default(),
+            ))
+            .unwrap();
+        renderer.drain_events();
+        press(&mut renderer, KeyCode::Esc);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 16)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let header = screen(&terminal).lines().nth(8).unwrap().to_string();
+        let column_of = |name: &str| header[..header.find(name).unwrap()].chars().count() as u16;
+        let names = |renderer: &Renderer| {
+            let result_state = &renderer.app_state.tabs[0];
+            let (rows, order) = renderer.app_state.table_rows(result_state).unwrap();
+            order.iter().map(|index| rows[*index][2].clone()).collect::<Vec<_>>()
+        };
+
+        click(&mut renderer, column_of("name"), 8);
+        assert_eq!(renderer.app_state.tabs[0].sort, Some((2, false)));
+        assert_eq!(names(&renderer), vec!["Anatomy Park", "Lawnmower Dog", "Pilot"]);
+
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        click(&mut renderer, column_of("id"), 8);
+        click(&mut renderer, column_of("id"), 8);
+        assert_eq!(renderer.app_state.tabs[0].sort, Some((0, true)));
+        assert_eq!(names(&renderer), vec!["Anatomy Park", "Lawnmower Dog", "Pilot"]);
+        // a click on the border above the names is not on a header
+        click(&mut renderer, column_of("id"), 7);
+        assert_eq!(renderer.app_state.tabs[0].sort, Some((0, true)));
+    }
+
+    #[test]
+    fn opens_switches_compares_and_closes_tabs() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into());
+        for (tab, name) in [(1, "Pilot"), (2, "Lawnmower Dog")] {
+            events_tx
+                .send(Event::Started(tab, format!("EPISODES::NAME({})", name)))
+                .unwrap();
+            events_tx
+                .send(Event::Completed(
+                    tab,
+                    RMResponseEnum::Episodes(EpisodePage {
+                        results: vec![Episode {
+                            id: tab as u32,
+                            name: name.into(),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }),
+                    QueryStats::default(),
+                ))
+                .unwrap();
+        }
+        renderer.drain_events();
+        assert_eq!(renderer.app_state.active, 1);
+
+        press(&mut renderer, KeyCode::Esc);
+        press(&mut renderer, KeyCode::Tab);
+        assert_eq!(renderer.app_state.active, 0);
+        press(&mut renderer, KeyCode::Char('s'));
+        press(&mut renderer, KeyCode::Tab);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 12)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let screen = screen(&terminal);
+        assert!(screen.contains("Pilot"));
+        assert!(screen.contains("Lawnmower Dog"));
+
+        press(&mut renderer, KeyCode::Char('w'));
+        assert_eq!(commands.try_recv().unwrap(), Command::CloseTab(2));
+        assert_eq!(renderer.app_state.tabs.len(), 1);
+        assert_eq!(renderer.app_state.compare, Some(1));
+    }
+
+    #[test]
+    fn shows_portraits_in_details() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into())
+            .with_portraits(Protocol::Blocks);
+        let url = "https://rickandmortyapi.com/api/character/avatar/1.jpeg";
+        events_tx.send(Event::Started(1, "CHARACTERS".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Characters(CharacterPage {
+                    results: vec![Character {
+                        id: 1,
+                        name: "Rick Sanchez".into(),
+                        image: url.into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                QueryStats::default(),
+            ))
+            .unwrap();
+        renderer.drain_events();
+        renderer.request_portrait();
+        assert!(commands.try_recv().is_err());
+
+        press(&mut renderer, KeyCode::Esc);
+        press(&mut renderer, KeyCode::Enter);
+        renderer.request_portrait();
+        renderer.request_portrait();
+        assert_eq!(commands.try_recv().unwrap(), Command::FetchPortrait(url.into()));
+        assert!(commands.try_recv().is_err());
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(screen(&terminal).contains("Loading portrait..."));
+
+        let image = crate::portrait::decode(include_bytes!("fixtures/portrait.png")).unwrap();
+        events_tx
+            .send(Event::Portrait(url.into(), Ok(image)))
+            .unwrap();
+        renderer.drain_events();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let blocks: Vec<_> = buffer.content.iter().filter(|cell| cell.symbol == "▀").collect();
+        assert!(!blocks.is_empty());
+        assert_eq!(blocks[0].fg, Color::Rgb(255, 0, 0));
+        assert_eq!(blocks.last().unwrap().bg, Color::Rgb(0, 0, 255));
+        assert!(screen(&terminal).contains("name: Rick Sanchez"));
+        assert!(renderer.placement.borrow().is_none());
+
+        // other protocols leave the place empty and draw after the frame
+        renderer.app_state.protocol = Protocol::Kitty;
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let placement = renderer.placement.take().unwrap();
+        assert_eq!(placement.0, url);
+        assert_eq!(placement.1.width, placement.1.height * 2);
+    }
+
+    #[test]
+    fn follows_the_keymap() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (_events_tx, events) = mpsc::channel();
+        let keymap = Keymap {
+            quit: "ctrl-c".into(),
+            help: "f1".into(),
+            ..Default::default()
+        };
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Mock".into())
+            .with_keymap(keymap);
+        press(&mut renderer, KeyCode::Esc);
+
+        assert!(press(&mut renderer, KeyCode::Char('q')));
+        press(&mut renderer, KeyCode::F(1));
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(screen(&terminal).contains("ctrl-c"));
+        press(&mut renderer, KeyCode::Esc);
+
+        assert!(!renderer.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert_eq!(commands.try_recv().unwrap(), Command::Quit);
+    }
+
+    #[test]
+    fn filters_rows_and_runs_commands() {
+        let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel();
+        let mut renderer = Renderer::new(tx, events, History::in_memory(), Columns::default(), "Direct".into());
+        let episodes = ["Pilot", "Lawnmower Dog", "Anatomy Park", "M. Night Shaym-Aliens!"]
+            .iter()
+            .zip(1..)
+            .map(|(name, id)| Episode {
+                id,
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        events_tx.send(Event::Started(1, "EPISODES".into())).unwrap();
+        events_tx
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage {
+                    results: episodes,
+                    ..Default::default()
+                }),
+                QueryStats::default(),
+            ))
+            .unwrap();
+        renderer.drain_events();
+        press(&mut renderer, KeyCode::Esc);
+
+        press(&mut renderer, KeyCode::Char('/'));
+        for c in "PARK".chars() {
+            press(&mut renderer, KeyCode::Char(c));
+        }
+        press(&mut renderer, KeyCode::Enter);
+        press(&mut renderer, KeyCode::Char('j'));
+        assert_eq!(renderer.app_state.tabs[0].selected, 0);
+        let mut terminal = Terminal::new(TestBackend::new(120, 16)).unwrap();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        let screen = screen(&terminal);
+        assert!(screen.contains("1 of 4 matching \"PARK\""));
+        assert!(screen.contains("> 3"));
+        assert!(!screen.contains("Pilot"));
+
+        press(&mut renderer, KeyCode::Char('/'));
+        press(&mut renderer, KeyCode::Esc);
+        assert_eq!(renderer.app_state.tabs[0].filter, "");
+
+        for line in ["export json out.json", "backend mock", "explode"] {
+            press(&mut renderer, KeyCode::Char(':'));
+            for c in line.chars() {
+                press(&mut renderer, KeyCode::Char(c));
+            }
+            press(&mut renderer, KeyCode::Enter);
+        }
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::Export(1, ExportFormat::Json, "out.json".into())
+        );
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::SwitchBackend(crate::config::Mode::Mock)
+        );
+        assert_eq!(
+            renderer.app_state.message.as_deref(),
+            Some("Parse error: unknown command `explode`")
+        );
+
+        events_tx
+            .send(Event::BackendSwitched(Ok("Mock".into())))
+            .unwrap();
+        renderer.drain_events();
+        terminal.draw(|f| ui(f, &renderer)).unwrap();
+        assert!(super::tests::screen(&terminal).contains(" NORMAL  Mock │ tab 1/1"));
+
+        press(&mut renderer, KeyCode::Char(':'));
+        press(&mut renderer, KeyCode::Char('q'));
+        assert!(!press(&mut renderer, KeyCode::Enter));
+        assert_eq!(commands.try_recv().unwrap(), Command::Quit);
     }
 }
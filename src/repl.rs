@@ -0,0 +1,489 @@
+use std::{
+    error::Error,
+    io::{self, Write},
+    sync::mpsc::Receiver,
+    time::Instant,
+};
+
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Editor, Helper,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    command_line::{self, LineCommand, COMMANDS},
+    engine::{Command, Event, TabId},
+    lib::{
+        error::RickError,
+        history::{History, HistoryEntry},
+        query_language::completion::complete,
+        rm_api::response::RMResponseEnum,
+        QueryStats,
+    },
+    renderer::page_position,
+    table::{self, Columns},
+};
+
+/// Every query of the REPL runs in this tab, so `:export` writes the last result
+const TAB: TabId = 1;
+const PROMPT: &str = "rick> ";
+/// How many of the newest history entries `:history` prints
+const HISTORY_SHOWN: usize = 20;
+/// The width tables are laid out in when the terminal's is unknown
+const DEFAULT_WIDTH: u16 = 100;
+
+/// A line based alternative to the full screen UI, talking to the engine
+/// the same way: each line is a query or a `:` command, and the results are
+/// printed as plain text.
+pub struct Repl {
+    tx: UnboundedSender<Command>,
+    events: Receiver<Event>,
+    history: History,
+    columns: Columns,
+    backend: String,
+    /// The last query run, saved by `:macro save` when no query is given
+    last_query: Option<String>,
+}
+
+impl Repl {
+    pub fn new(
+        tx: UnboundedSender<Command>,
+        events: Receiver<Event>,
+        history: History,
+        columns: Columns,
+        backend: String,
+    ) -> Self {
+        Repl {
+            tx,
+            events,
+            history,
+            columns,
+            backend,
+            last_query: None,
+        }
+    }
+
+    /// Reads and runs lines until `:quit` or end of input, then stops the engine.
+    pub fn start(mut self) -> Result<(), Box<dyn Error>> {
+        let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+        editor.set_helper(Some(ReplHelper));
+        for entry in self.history.entries() {
+            editor.add_history_entry(entry.query.as_str())?;
+        }
+        println!(
+            "Querying the {} backend, :help lists the commands",
+            self.backend
+        );
+        let mut stdout = io::stdout();
+        let result = loop {
+            let input = match editor.readline(PROMPT) {
+                Ok(line) => join_lines(&line),
+                // Ctrl-C drops the line being typed, like a shell
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break Ok(()),
+                Err(err) => break Err(err.into()),
+            };
+            if input.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(input.as_str())?;
+            match self.eval(&input, &mut stdout) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err.into()),
+            }
+        };
+        self.send(Command::Quit);
+        result
+    }
+
+    /// Runs a query or a `:` command, printing what came of it to `out`.
+    /// Returns whether to read another line.
+    fn eval(&mut self, input: &str, out: &mut impl Write) -> io::Result<bool> {
+        let Some(line) = input.strip_prefix(':') else {
+            self.run_query(input, out)?;
+            return Ok(true);
+        };
+        let command = match command_line::parse(line) {
+            Ok(command) => command,
+            Err(error) => {
+                writeln!(out, "{}: {}", error.kind(), error)?;
+                return Ok(true);
+            }
+        };
+        match command {
+            LineCommand::Export(format, path) => {
+                self.send(Command::Export(TAB, format, path));
+                let message = self.wait(|event| match event {
+                    Event::Exported(Ok((path, rows))) => {
+                        Some(format!("Exported {} rows to {}", rows, path.display()))
+                    }
+                    Event::Exported(Err(error)) => Some(format!("{}: {}", error.kind(), error)),
+                    _ => None,
+                });
+                self.print(message, out)?;
+            }
+            LineCommand::Backend(mode) => {
+                self.send(Command::SwitchBackend(mode));
+                let switched = self.wait(|event| match event {
+                    Event::BackendSwitched(switched) => Some(switched),
+                    _ => None,
+                });
+                let message = switched.map(|switched| match switched {
+                    Ok(backend) => {
+                        let message = format!("Switched to the {} backend", backend);
+                        self.backend = backend;
+                        message
+                    }
+                    Err(error) => format!("{}: {}", error.kind(), error),
+                });
+                self.print(message, out)?;
+            }
+            LineCommand::History => {
+                let entries = self.history.entries();
+                for entry in &entries[entries.len().saturating_sub(HISTORY_SHOWN)..] {
+                    let outcome = match entry.result_count {
+                        Some(count) => format!("{} results", count),
+                        None => "failed".to_string(),
+                    };
+                    writeln!(
+                        out,
+                        "{}  ({}, {} ms)",
+                        entry.query, outcome, entry.duration_ms
+                    )?;
+                }
+            }
+            LineCommand::SaveMacro(signature, query) => {
+                match query.or_else(|| self.last_query.clone()) {
+                    Some(query) => {
+                        self.send(Command::SaveMacro(signature, query));
+                        self.print_macros(out)?;
+                    }
+                    None => writeln!(out, "Run a query first, or give the query to save")?,
+                }
+            }
+            LineCommand::RemoveMacro(name) => {
+                self.send(Command::RemoveMacro(name));
+                self.print_macros(out)?;
+            }
+            LineCommand::ListMacros => {
+                self.send(Command::ListMacros);
+                self.print_macros(out)?;
+            }
+            LineCommand::Help => {
+                for (command, description) in COMMANDS {
+                    writeln!(out, "{:<44}{}", command, description)?;
+                }
+                writeln!(
+                    out,
+                    "End a line with `\\`, `::`, `|` or `;`, or leave a `(` open, to carry on below"
+                )?;
+            }
+            LineCommand::CloseTab => {
+                self.send(Command::CloseTab(TAB));
+                self.last_query = None;
+            }
+            LineCommand::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn run_query(&mut self, query: &str, out: &mut impl Write) -> io::Result<()> {
+        self.send(Command::RunQuery(TAB, query.to_string()));
+        self.last_query = Some(query.to_string());
+        let started_at = Instant::now();
+        let mut ran = query.to_string();
+        let outcome = self.wait(|event| match event {
+            // the engine may add to the query, e.g a default LIMIT
+            Event::Started(_, query) => {
+                ran = query;
+                None
+            }
+            Event::Completed(_, response, stats) => Some(Outcome::Completed(response, stats)),
+            Event::Explained(_, explanation) => Some(Outcome::Explained(explanation)),
+            Event::Failed(_, error) => Some(Outcome::Failed(error)),
+            _ => None,
+        });
+        let result_count = match &outcome {
+            Some(Outcome::Completed(response, _)) => Some(response.result_count()),
+            Some(Outcome::Failed(_)) => None,
+            // explaining runs nothing worth keeping in the history
+            Some(Outcome::Explained(_)) | None => return self.print_outcome(&ran, outcome, out),
+        };
+        let entry = HistoryEntry {
+            query: query.to_string(),
+            timestamp: chrono::Utc::now(),
+            result_count,
+            duration_ms: started_at.elapsed().as_millis() as u64,
+        };
+        if let Err(err) = self.history.record(entry) {
+            writeln!(out, "could not save history: {}", err)?;
+        }
+        self.print_outcome(&ran, outcome, out)
+    }
+
+    fn print_outcome(
+        &self,
+        query: &str,
+        outcome: Option<Outcome>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match outcome {
+            Some(Outcome::Completed(response, stats)) => {
+                self.print_result(query, &response, &stats, out)
+            }
+            Some(Outcome::Explained(explanation)) => writeln!(out, "{}", explanation),
+            Some(Outcome::Failed(error)) => writeln!(out, "{}: {}", error.kind(), error),
+            None => Ok(()),
+        }
+    }
+
+    fn print_result(
+        &self,
+        query: &str,
+        response: &RMResponseEnum,
+        stats: &QueryStats,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let header = self.columns.for_response(response).to_vec();
+        let rows = table::rows(response, &header);
+        let width = crossterm::terminal::size()
+            .map(|(width, _)| width)
+            .unwrap_or(DEFAULT_WIDTH);
+        for line in table::text_table(&header, &rows, width) {
+            writeln!(out, "{}", line)?;
+        }
+        let rows = if stats.fetched == stats.shown {
+            format!("{} rows", stats.shown)
+        } else {
+            format!("{} → {} rows", stats.fetched, stats.shown)
+        };
+        writeln!(
+            out,
+            "{} │ {} │ {:.2}s │ {}",
+            page_position(query, response),
+            rows,
+            stats.elapsed.as_secs_f32(),
+            if stats.from_cache { "cached" } else { "fresh" }
+        )
+    }
+
+    fn print_macros(&self, out: &mut impl Write) -> io::Result<()> {
+        let message = self.wait(|event| match event {
+            Event::Macros(Ok(message)) => Some(message),
+            Event::Macros(Err(error)) => Some(format!("{}: {}", error.kind(), error)),
+            _ => None,
+        });
+        self.print(message, out)
+    }
+
+    fn print(&self, message: Option<String>, out: &mut impl Write) -> io::Result<()> {
+        match message {
+            Some(message) => writeln!(out, "{}", message),
+            None => Ok(()),
+        }
+    }
+
+    /// Blocks until the engine sends an event `pick` takes, `None` once
+    /// the engine is gone.
+    fn wait<T>(&self, mut pick: impl FnMut(Event) -> Option<T>) -> Option<T> {
+        loop {
+            if let Some(picked) = pick(self.events.recv().ok()?) {
+                return Some(picked);
+            }
+        }
+    }
+
+    fn send(&self, command: Command) {
+        let _ = self.tx.send(command);
+    }
+}
+
+/// How a query sent to the engine ended
+enum Outcome {
+    Completed(RMResponseEnum, QueryStats),
+    Explained(String),
+    Failed(RickError),
+}
+
+/// Completes queries like the full screen UI, and keeps reading lines
+/// while the input is obviously unfinished.
+struct ReplHelper;
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let completion = complete(line, pos);
+        Ok((completion.start, completion.candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(if is_complete(ctx.input()) {
+            ValidationResult::Valid(None)
+        } else {
+            ValidationResult::Incomplete
+        })
+    }
+}
+
+impl Helper for ReplHelper {}
+
+/// Whether the input can run, or carries on in the next line.
+fn is_complete(input: &str) -> bool {
+    if input.trim_start().starts_with(':') {
+        return true;
+    }
+    let input = input.trim_end();
+    let unfinished = ["\\", "::", "|", ";"]
+        .iter()
+        .any(|ending| input.ends_with(ending));
+    !unfinished && input.matches('(').count() <= input.matches(')').count()
+}
+
+/// The lines of a multiline input as one query.
+fn join_lines(input: &str) -> String {
+    input
+        .lines()
+        .map(|line| line.trim().trim_end_matches('\\').trim_end())
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::entities::{Episode, EpisodePage, Info};
+
+    fn repl() -> (
+        Repl,
+        tokio::sync::mpsc::UnboundedReceiver<Command>,
+        std::sync::mpsc::Sender<Event>,
+    ) {
+        let (tx, commands) = tokio::sync::mpsc::unbounded_channel();
+        let (events_tx, events) = std::sync::mpsc::channel();
+        let repl = Repl::new(
+            tx,
+            events,
+            History::in_memory(),
+            Columns::default(),
+            "Mock".into(),
+        );
+        (repl, commands, events_tx)
+    }
+
+    fn eval(repl: &mut Repl, input: &str) -> (bool, String) {
+        let mut out = vec![];
+        let more = repl.eval(input, &mut out).unwrap();
+        (more, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn prints_results_and_records_history() {
+        let (mut repl, mut commands, events) = repl();
+        events
+            .send(Event::Started(1, "EPISODES::LIMIT(1)".into()))
+            .unwrap();
+        events
+            .send(Event::Completed(
+                1,
+                RMResponseEnum::Episodes(EpisodePage {
+                    info: Info {
+                        count: 51,
+                        pages: 3,
+                        ..Default::default()
+                    },
+                    results: vec![Episode {
+                        id: 1,
+                        name: "Pilot".into(),
+                        ..Default::default()
+                    }],
+                }),
+                QueryStats {
+                    fetched: 20,
+                    shown: 1,
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        let (more, printed) = eval(&mut repl, "EPISODES");
+        assert!(more);
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::RunQuery(TAB, "EPISODES".into())
+        );
+        assert!(printed.contains("Pilot"));
+        assert!(printed.contains("page 1 of 3, 51 total │ 20 → 1 rows"));
+        assert_eq!(repl.history.entries()[0].result_count, Some(1));
+
+        events
+            .send(Event::Failed(1, RickError::Parse("no".into())))
+            .unwrap();
+        let (_, printed) = eval(&mut repl, "NOTAROOT");
+        assert_eq!(printed, "Parse error: no\n");
+        assert_eq!(repl.history.entries()[1].result_count, None);
+        let (_, printed) = eval(&mut repl, ":history");
+        assert!(printed.contains("NOTAROOT  (failed"));
+    }
+
+    #[test]
+    fn runs_commands() {
+        let (mut repl, mut commands, events) = repl();
+        let (_, printed) = eval(&mut repl, ":macro save pilot");
+        assert!(printed.starts_with("Run a query first"));
+
+        repl.last_query = Some("EPISODES::NAME(Pilot)".into());
+        events
+            .send(Event::Macros(Ok("Saved @pilot".into())))
+            .unwrap();
+        let (_, printed) = eval(&mut repl, ":macro save pilot");
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            Command::SaveMacro("pilot".into(), "EPISODES::NAME(Pilot)".into())
+        );
+        assert_eq!(printed, "Saved @pilot\n");
+
+        events
+            .send(Event::BackendSwitched(Ok("Direct".into())))
+            .unwrap();
+        eval(&mut repl, ":backend direct");
+        assert_eq!(repl.backend, "Direct");
+
+        assert!(eval(&mut repl, ":nope").1.contains("unknown command"));
+        assert!(!eval(&mut repl, ":q").0);
+    }
+
+    #[test]
+    fn carries_unfinished_lines_on() {
+        assert!(is_complete("CHARACTERS::NAME(rick)"));
+        assert!(is_complete(":export json"));
+        assert!(!is_complete("CHARACTERS::"));
+        assert!(!is_complete("LET pilot = EPISODES::NAME(Pilot);"));
+        assert!(!is_complete("EPISODES |"));
+        assert!(!is_complete("CHARACTERS::SORT(ASC,"));
+        assert!(!is_complete("CHARACTERS \\"));
+        assert_eq!(
+            join_lines("CHARACTERS::\n  NAME(rick) \\\n  ::LIMIT(2)"),
+            "CHARACTERS::NAME(rick)::LIMIT(2)"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+
+use crate::lib::{
+    error::RickError,
+    rm_api::{
+        entities::{Character, Entity, Episode, Location},
+        response::RMResponseEnum,
+    },
+};
+
+/// Appended to a list field, e.g `residents_count`, to show how many items it holds
+const COUNT_SUFFIX: &str = "_count";
+
+/// The columns shown for each root, either the defaults or set with `--columns`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Columns {
+    pub characters: Vec<String>,
+    pub locations: Vec<String>,
+    pub episodes: Vec<String>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        let columns = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Columns {
+            characters: columns(&["id", "name", "status", "species", "gender", "episode_count"]),
+            locations: columns(&["id", "name", "type", "dimension", "residents_count"]),
+            episodes: columns(&["id", "episode", "name", "air_date", "characters_count"]),
+        }
+    }
+}
+
+impl Columns {
+    /// Applies a `ROOT=field,field` spec, e.g `LOCATIONS=name,dimension`.
+    pub fn set(&mut self, spec: &str) -> Result<(), RickError> {
+        let (root, fields) = spec
+            .split_once('=')
+            .ok_or_else(|| RickError::Parse(format!("expected ROOT=field,field in `{}`", spec)))?;
+        let (columns, field_names, entity) = match root.trim().to_uppercase().as_str() {
+            "CHARACTERS" => (&mut self.characters, Character::FIELD_NAMES, "characters"),
+            "LOCATIONS" => (&mut self.locations, Location::FIELD_NAMES, "locations"),
+            "EPISODES" => (&mut self.episodes, Episode::FIELD_NAMES, "episodes"),
+            _ => return Err(RickError::Parse(format!("unknown root `{}`", root))),
+        };
+
+        let mut parsed = vec![];
+        for field in fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+        {
+            let name = field.strip_suffix(COUNT_SUFFIX).unwrap_or(field);
+            if !field_names.contains(&name) {
+                return Err(RickError::UnknownField {
+                    entity: entity.to_string(),
+                    field: field.to_string(),
+                });
+            }
+            parsed.push(field.to_string());
+        }
+        if parsed.is_empty() {
+            return Err(RickError::Parse(format!("no columns given for {}", root)));
+        }
+        *columns = parsed;
+        Ok(())
+    }
+
+    pub fn for_response(&self, response: &RMResponseEnum) -> &[String] {
+        match response {
+            RMResponseEnum::Characters(_) => &self.characters,
+            RMResponseEnum::Locations(_) => &self.locations,
+            RMResponseEnum::Episodes(_) => &self.episodes,
+        }
+    }
+}
+
+/// The cells of every result, in the order the API returned them.
+pub fn rows(response: &RMResponseEnum, columns: &[String]) -> Vec<Vec<String>> {
+    match response {
+        RMResponseEnum::Characters(page) => entity_rows(&page.results, columns),
+        RMResponseEnum::Locations(page) => entity_rows(&page.results, columns),
+        RMResponseEnum::Episodes(page) => entity_rows(&page.results, columns),
+    }
+}
+
+fn entity_rows<T: Entity>(results: &[T], columns: &[String]) -> Vec<Vec<String>> {
+    results
+        .iter()
+        .map(|result| columns.iter().map(|column| cell(result, column)).collect())
+        .collect()
+}
+
+fn cell<T: Entity>(entity: &T, column: &str) -> String {
+    match column.strip_suffix(COUNT_SUFFIX) {
+        Some(list) => entity
+            .field(list)
+            .map(|ids| {
+                ids.split(',')
+                    .filter(|id| !id.is_empty())
+                    .count()
+                    .to_string()
+            })
+            .unwrap_or_default(),
+        None => entity.field(column).unwrap_or_default(),
+    }
+}
+
+/// The indices of `rows` in display order, sorted by `column` when given.
+pub fn row_order(rows: &[Vec<String>], sort: Option<(usize, bool)>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    if let Some((column, descending)) = sort {
+        order.sort_by(|a, b| {
+            let ordering = compare_cells(&rows[*a][column], &rows[*b][column]);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+    order
+}
+
+/// Keeps the indices in `order` of the rows with a cell containing
+/// `filter`, ignoring case. An empty filter keeps every row.
+pub fn filter_rows(rows: &[Vec<String>], order: Vec<usize>, filter: &str) -> Vec<usize> {
+    if filter.is_empty() {
+        return order;
+    }
+    let filter = filter.to_lowercase();
+    order
+        .into_iter()
+        .filter(|index| rows[*index].iter().any(|cell| cell.to_lowercase().contains(&filter)))
+        .collect()
+}
+
+/// Compares numbers as numbers and everything else case insensitively.
+fn compare_cells(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+/// Fits the columns into `available` cells, taking from the widest column
+/// until they fit so short columns like ids are never cut.
+pub fn column_widths(header: &[String], rows: &[Vec<String>], available: u16) -> Vec<u16> {
+    let mut widths: Vec<u16> = header
+        .iter()
+        .enumerate()
+        .map(|(column, title)| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .chain([title.chars().count()])
+                .max()
+                .unwrap_or(0) as u16
+        })
+        .collect();
+    while widths.iter().sum::<u16>() > available {
+        let Some(widest) = widths.iter_mut().max() else {
+            break;
+        };
+        if *widest <= 1 {
+            break;
+        }
+        *widest -= 1;
+    }
+    widths
+}
+/// Lays the rows out as plain text lines under the header, cutting cells
+/// that do not fit in `available` characters with `…`.
+pub fn text_table(header: &[String], rows: &[Vec<String>], available: u16) -> Vec<String> {
+    let spacing = header.len().saturating_sub(1) * 2;
+    let widths = column_widths(header, rows, available.saturating_sub(spacing as u16));
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| fit(cell, *width as usize))
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+    let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width as usize)).collect();
+    [line(header), rule.join("  ")]
+        .into_iter()
+        .chain(rows.iter().map(|row| line(row)))
+        .collect()
+}
+
+fn fit(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return format!("{:<width$}", cell, width = width);
+    }
+    let cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::rm_api::entities::LocationPage;
+
+    fn locations() -> RMResponseEnum {
+        RMResponseEnum::Locations(LocationPage {
+            results: vec![
+                Location {
+                    id: 2,
+                    name: "Abadango".into(),
+                    residents: vec!["https://rickandmortyapi.com/api/character/6".into()],
+                    ..Default::default()
+                },
+                Location {
+                    id: 10,
+                    name: "anatomy Park".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn builds_rows_with_counts() {
+        let columns = vec!["id".to_string(), "residents_count".to_string()];
+        assert_eq!(
+            rows(&locations(), &columns),
+            vec![vec!["2", "1"], vec!["10", "0"]]
+        );
+    }
+
+    #[test]
+    fn sorts_numbers_and_text() {
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let rows = rows(&locations(), &columns);
+        assert_eq!(row_order(&rows, None), vec![0, 1]);
+        assert_eq!(row_order(&rows, Some((0, true))), vec![1, 0]);
+        assert_eq!(row_order(&rows, Some((1, false))), vec![0, 1]);
+    }
+
+    #[test]
+    fn filters_rows() {
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let rows = rows(&locations(), &columns);
+        assert_eq!(filter_rows(&rows, vec![1, 0], ""), vec![1, 0]);
+        assert_eq!(filter_rows(&rows, vec![1, 0], "ANATOMY"), vec![1]);
+        assert_eq!(filter_rows(&rows, vec![0, 1], "2"), vec![0]);
+        assert!(filter_rows(&rows, vec![0, 1], "citadel").is_empty());
+    }
+
+    #[test]
+    fn shrinks_the_widest_columns() {
+        let header = vec!["id".to_string(), "name".to_string()];
+        let rows = vec![vec!["1".to_string(), "Rick Sanchez".to_string()]];
+        assert_eq!(column_widths(&header, &rows, 20), vec![2, 12]);
+        assert_eq!(column_widths(&header, &rows, 8), vec![2, 6]);
+    }
+
+    #[test]
+    fn lays_out_text_tables() {
+        let header = vec!["id".to_string(), "name".to_string()];
+        let rows = vec![
+            vec!["1".to_string(), "Rick Sanchez".to_string()],
+            vec!["2".to_string(), "Morty".to_string()],
+        ];
+        assert_eq!(
+            text_table(&header, &rows, 80),
+            vec!["id  name", "──  ────────────", "1   Rick Sanchez", "2   Morty"]
+        );
+        assert_eq!(text_table(&header, &rows, 10)[2], "1   Rick …");
+    }
+
+    #[test]
+    fn configures_columns() {
+        let mut columns = Columns::default();
+        columns.set("episodes=name, characters_count").unwrap();
+        assert_eq!(columns.episodes, vec!["name", "characters_count"]);
+        assert!(matches!(
+            columns.set("EPISODES=status"),
+            Err(RickError::UnknownField { .. })
+        ));
+        assert!(columns.set("PLANETS=name").is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+use crate::lib::error::RickError;
+
+/// The built in themes, any other `--theme` is read as a file
+pub const PRESETS: &[&str] = &["dark", "light", "high-contrast", "no-colour"];
+
+/// The styles the UI is drawn with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// The input, table headers, field names, keys and the active tab
+    pub accent: Style,
+    /// Secondary text, e.g history details and query punctuation
+    pub muted: Style,
+    /// Messages and the loading indicator
+    pub info: Style,
+    /// Failures that come from the API or the network
+    pub error: Style,
+    /// Mistakes in the query, which are the user's to fix
+    pub warning: Style,
+    /// The selected row
+    pub selection: Style,
+    /// The mode in the status bar
+    pub status: Style,
+    /// Queries listed in the history
+    pub query: Style,
+    /// The parts of a query in the input
+    pub root: Style,
+    pub operation: Style,
+    pub operand: Style,
+    /// Cell styles by field and value, the first match wins
+    pub rules: Vec<FieldRule>,
+}
+
+/// Styles the cells of a field, e.g `status` when it is `Dead`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldRule {
+    pub field: String,
+    /// The value to match, ignoring case; `None` matches every value
+    pub value: Option<String>,
+    pub style: Style,
+}
+
+impl FieldRule {
+    fn new(field: &str, value: Option<&str>, style: Style) -> Self {
+        FieldRule {
+            field: field.to_string(),
+            value: value.map(str::to_string),
+            style,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+fn fg(color: Color) -> Style {
+    Style::default().fg(color)
+}
+
+fn bold(style: Style) -> Style {
+    style.add_modifier(Modifier::BOLD)
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            accent: bold(fg(Color::Yellow)),
+            muted: fg(Color::DarkGray),
+            info: fg(Color::Cyan),
+            error: fg(Color::Red),
+            warning: fg(Color::Yellow),
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            status: bold(fg(Color::Black).bg(Color::Cyan)),
+            query: fg(Color::Green),
+            root: bold(fg(Color::Magenta)),
+            operation: bold(fg(Color::Cyan)),
+            operand: fg(Color::Yellow),
+            rules: vec![
+                FieldRule::new("status", Some("Alive"), fg(Color::Green)),
+                FieldRule::new("status", Some("Dead"), fg(Color::Red)),
+                FieldRule::new("status", None, fg(Color::Yellow)),
+                FieldRule::new("name", None, fg(Color::Green)),
+            ],
+        }
+    }
+
+    /// Darker colours that stay readable on a light background
+    pub fn light() -> Self {
+        Theme {
+            accent: bold(fg(Color::Blue)),
+            muted: fg(Color::DarkGray),
+            info: fg(Color::Blue),
+            error: fg(Color::Red),
+            warning: fg(Color::Magenta),
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            status: bold(fg(Color::White).bg(Color::Blue)),
+            query: fg(Color::Black),
+            root: bold(fg(Color::Magenta)),
+            operation: bold(fg(Color::Blue)),
+            operand: fg(Color::Red),
+            rules: vec![
+                FieldRule::new("status", Some("Alive"), fg(Color::Green)),
+                FieldRule::new("status", Some("Dead"), fg(Color::Red)),
+                FieldRule::new("status", None, fg(Color::Magenta)),
+                FieldRule::new("name", None, fg(Color::Blue)),
+            ],
+        }
+    }
+
+    /// Bright colours and bold text
+    pub fn high_contrast() -> Self {
+        Theme {
+            accent: bold(fg(Color::LightYellow)),
+            muted: fg(Color::White),
+            info: bold(fg(Color::LightCyan)),
+            error: bold(fg(Color::LightRed)),
+            warning: bold(fg(Color::LightYellow)),
+            selection: bold(Style::default().add_modifier(Modifier::REVERSED)),
+            status: bold(fg(Color::Black).bg(Color::White)),
+            query: bold(fg(Color::White)),
+            root: bold(fg(Color::LightMagenta)),
+            operation: bold(fg(Color::LightCyan)),
+            operand: fg(Color::White),
+            rules: vec![
+                FieldRule::new("status", Some("Alive"), bold(fg(Color::LightGreen))),
+                FieldRule::new("status", Some("Dead"), bold(fg(Color::LightRed))),
+                FieldRule::new("status", None, fg(Color::LightYellow)),
+                FieldRule::new("name", None, bold(fg(Color::White))),
+            ],
+        }
+    }
+
+    /// Only bold, dim, underlined and reversed text
+    pub fn no_colour() -> Self {
+        Theme::dark().without_colours()
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "no-colour" | "no-color" => Some(Theme::no_colour()),
+            _ => None,
+        }
+    }
+
+    /// Reads a preset by name, or else a theme file.
+    pub fn load(spec: &str) -> Result<Self, RickError> {
+        if let Some(theme) = Theme::preset(spec) {
+            return Ok(theme);
+        }
+        let path = Path::new(spec);
+        let contents = fs::read_to_string(path).map_err(|err| {
+            RickError::Io(format!(
+                "`{}` is neither a theme ({}) nor a readable file ({})",
+                spec,
+                PRESETS.join(", "),
+                err
+            ))
+        })?;
+        Theme::parse(&contents)
+            .map_err(|err| RickError::Parse(format!("in theme {}: {}", path.display(), err)))
+    }
+
+    /// Reads a theme file: a preset to start from, the styles that replace
+    /// its own and rules that take precedence over its rules.
+    pub fn parse(contents: &str) -> Result<Self, RickError> {
+        let file: ThemeFile =
+            toml::from_str(contents).map_err(|err| RickError::Parse(err.message().to_string()))?;
+        let preset = file.preset.as_deref().unwrap_or("dark");
+        let mut theme = Theme::preset(preset)
+            .ok_or_else(|| RickError::Parse(format!("unknown preset `{}`", preset)))?;
+        for (name, spec) in &file.styles {
+            let style = spec.to_style()?;
+            *theme
+                .style_mut(name)
+                .ok_or_else(|| RickError::Parse(format!("unknown style `{}`", name)))? = style;
+        }
+        let mut rules = file
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok(FieldRule {
+                    field: rule.field.clone(),
+                    value: rule.value.clone(),
+                    style: rule.style.to_style()?,
+                })
+            })
+            .collect::<Result<Vec<_>, RickError>>()?;
+        rules.append(&mut theme.rules);
+        theme.rules = rules;
+        Ok(theme)
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        match name {
+            "accent" => Some(&mut self.accent),
+            "muted" => Some(&mut self.muted),
+            "info" => Some(&mut self.info),
+            "error" => Some(&mut self.error),
+            "warning" => Some(&mut self.warning),
+            "selection" => Some(&mut self.selection),
+            "status" => Some(&mut self.status),
+            "query" => Some(&mut self.query),
+            "root" => Some(&mut self.root),
+            "operation" => Some(&mut self.operation),
+            "operand" => Some(&mut self.operand),
+            _ => None,
+        }
+    }
+
+    /// Drops every colour and keeps the modifiers, for `NO_COLOR`.
+    pub fn without_colours(mut self) -> Self {
+        let strip = |style: &mut Style| {
+            style.fg = None;
+            style.bg = None;
+        };
+        for style in [
+            &mut self.accent,
+            &mut self.muted,
+            &mut self.info,
+            &mut self.error,
+            &mut self.warning,
+            &mut self.selection,
+            &mut self.status,
+            &mut self.query,
+            &mut self.root,
+            &mut self.operation,
+            &mut self.operand,
+        ] {
+            strip(style);
+        }
+        // keep the status bar's mode apart from the rest of it
+        self.status = self.status.add_modifier(Modifier::REVERSED);
+        self.muted = self.muted.add_modifier(Modifier::DIM);
+        self.rules.retain_mut(|rule| {
+            strip(&mut rule.style);
+            rule.style != Style::default()
+        });
+        self
+    }
+
+    /// The style of a cell, from the first rule matching its field and value.
+    pub fn cell_style(&self, field: &str, value: &str) -> Style {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.field == field
+                    && rule
+                        .value
+                        .as_ref()
+                        .is_none_or(|expected| expected.eq_ignore_ascii_case(value))
+            })
+            .map(|rule| rule.style)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    preset: Option<String>,
+    #[serde(default)]
+    styles: HashMap<String, StyleSpec>,
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+struct RuleSpec {
+    field: String,
+    value: Option<String>,
+    #[serde(flatten)]
+    style: StyleSpec,
+}
+
+/// A style as written in a theme file, e.g `{ fg = "green", modifiers = ["bold"] }`
+#[derive(Deserialize)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+impl StyleSpec {
+    fn to_style(&self) -> Result<Style, RickError> {
+        let mut style = Style::default();
+        if let Some(color) = &self.fg {
+            style = style.fg(parse_color(color)?);
+        }
+        if let Some(color) = &self.bg {
+            style = style.bg(parse_color(color)?);
+        }
+        for modifier in &self.modifiers {
+            style = style.add_modifier(parse_modifier(modifier)?);
+        }
+        Ok(style)
+    }
+}
+
+/// A colour name like `light-red`, a hex colour like `#ff8800` or a 256 colour index.
+fn parse_color(color: &str) -> Result<Color, RickError> {
+    let name: String = color
+        .to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .collect();
+    let parsed = match name.as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        hex if hex.starts_with('#') && hex.len() == 7 => {
+            // checked first, so that the channels are sliced at character boundaries
+            if !hex[1..].chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(RickError::Parse(format!("invalid colour `{}`", color)));
+            }
+            let channel = |range| u8::from_str_radix(&hex[range], 16).ok();
+            match (channel(1..3), channel(3..5), channel(5..7)) {
+                (Some(r), Some(g), Some(b)) => Color::Rgb(r, g, b),
+                _ => return Err(RickError::Parse(format!("invalid colour `{}`", color))),
+            }
+        }
+        index => match index.parse::<u8>() {
+            Ok(index) => Color::Indexed(index),
+            Err(_) => return Err(RickError::Parse(format!("unknown colour `{}`", color))),
+        },
+    };
+    Ok(parsed)
+}
+
+fn parse_modifier(modifier: &str) -> Result<Modifier, RickError> {
+    match modifier.to_lowercase().as_str() {
+        "bold" => Ok(Modifier::BOLD),
+        "dim" => Ok(Modifier::DIM),
+        "italic" => Ok(Modifier::ITALIC),
+        "underlined" | "underline" => Ok(Modifier::UNDERLINED),
+        "reversed" => Ok(Modifier::REVERSED),
+        "crossed_out" | "crossed-out" => Ok(Modifier::CROSSED_OUT),
+        _ => Err(RickError::Parse(format!("unknown modifier `{}`", modifier))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_presets() {
+        for preset in PRESETS {
+            assert!(Theme::load(preset).is_ok());
+        }
+        assert_eq!(Theme::load("dark").unwrap(), Theme::default());
+        assert!(matches!(
+            Theme::load("/no/such/theme.toml"),
+            Err(RickError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn styles_cells_by_rule() {
+        let theme = Theme::dark();
+        assert_eq!(theme.cell_style("status", "dead"), fg(Color::Red));
+        assert_eq!(theme.cell_style("status", "unknown"), fg(Color::Yellow));
+        assert_eq!(theme.cell_style("id", "1"), Style::default());
+    }
+
+    #[test]
+    fn parses_theme_files() {
+        let theme = Theme::parse(
+            r##"
+            preset = "light"
+
+            [styles]
+            accent = { fg = "light-green", bg = "#102030", modifiers = ["italic"] }
+
+            [[rules]]
+            field = "species"
+            value = "Alien"
+            fg = "208"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            theme.accent,
+            Style::default()
+                .fg(Color::LightGreen)
+                .bg(Color::Rgb(0x10, 0x20, 0x30))
+                .add_modifier(Modifier::ITALIC)
+        );
+        assert_eq!(
+            theme.cell_style("species", "alien"),
+            fg(Color::Indexed(208))
+        );
+        // the preset's rules still apply after the file's
+        assert_eq!(theme.cell_style("name", "Rick"), fg(Color::Blue));
+
+        assert!(Theme::parse("preset = \"solarized\"").is_err());
+        assert!(Theme::parse("[styles]\nbackground = { fg = \"red\" }").is_err());
+        assert!(Theme::parse("[styles]\naccent = { fg = \"redish\" }").is_err());
+        assert!(Theme::parse("[styles]\naccent = { fg = \"#aééb\" }").is_err());
+    }
+
+    #[test]
+    fn drops_colours() {
+        let theme = Theme::high_contrast().without_colours();
+        assert_eq!(theme.accent, Style::default().add_modifier(Modifier::BOLD));
+        assert!(theme.rules.iter().all(|rule| rule.style.fg.is_none()));
+        assert_eq!(theme.cell_style("status", "Alive"), bold(Style::default()));
+        assert_eq!(
+            Theme::no_colour().cell_style("status", "Alive"),
+            Style::default()
+        );
+    }
+}